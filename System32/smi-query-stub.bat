@@ -0,0 +1 @@
+@echo 0, NVIDIA GeForce GTX 1070 Ti, GPU-5f3c2d4e-8a1b-4c6d-9e0f-123456789abc, 00000000:01:00.0, 0x1B8210DE, 8192, 123.45, 86.04.85.00.63, WDDM
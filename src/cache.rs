@@ -0,0 +1,313 @@
+//! On-disk cache of the driver lookup responses.
+//!
+//! A response younger than the time to live is answered from the cache
+//! without a request. An older one is revalidated with a conditional request,
+//! so that an unchanged response is not transferred again. In offline mode
+//! every response is answered from the cache regardless of its age.
+
+use crate::{DriverCheckError, HttpClient, HttpFetcher};
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default time to live of the cached responses.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A cached response, stored as JSON in a file named by the URL hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    /// When the response was fetched or last revalidated, in seconds since
+    /// the Unix epoch.
+    fetched: u64,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+impl CacheEntry {
+    /// Returns the time since the response was fetched or revalidated.
+    fn age(&self) -> Duration {
+        Duration::from_secs(unix_time().saturating_sub(self.fetched))
+    }
+}
+
+/// Returns the directory of the cache in the per-user cache directory, if
+/// the directory is known.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("geforcedrvchk3"))
+}
+
+/// Fetcher answering from the on-disk cache when possible and fetching the
+/// pages with the shared `HttpClient` otherwise.
+#[derive(Debug)]
+pub struct ResponseCache {
+    directory: PathBuf,
+    ttl: Duration,
+    offline: bool,
+    /// Age of the oldest response answered from the cache.
+    age: Cell<Option<Duration>>,
+}
+
+impl ResponseCache {
+    /// Creates a cache storing the responses in the directory with the
+    /// default time to live.
+    pub fn new(directory: impl Into<PathBuf>) -> ResponseCache {
+        ResponseCache {
+            directory: directory.into(),
+            ttl: DEFAULT_CACHE_TTL,
+            offline: false,
+            age: Cell::new(None),
+        }
+    }
+
+    /// Sets how long a response is used without revalidating it. Zero
+    /// revalidates every response.
+    pub fn ttl(mut self, ttl: Duration) -> ResponseCache {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the offline mode, in which no requests are made and every
+    /// response is answered from the cache.
+    pub fn offline(mut self, offline: bool) -> ResponseCache {
+        self.offline = offline;
+        self
+    }
+
+    /// Returns the directory of the cache.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the age of the oldest response answered from the cache
+    /// without a request, if any.
+    pub fn cached_age(&self) -> Option<Duration> {
+        self.age.get()
+    }
+
+    fn entry_path(&self, url: &str) -> PathBuf {
+        let hash: String = Sha256::digest(url.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        self.directory.join(format!("{hash}.json"))
+    }
+
+    /// Reads the cached response of the URL. A missing or unreadable entry
+    /// is a cache miss.
+    fn read(&self, url: &str) -> Option<CacheEntry> {
+        let text = fs::read_to_string(self.entry_path(url)).ok()?;
+        serde_json::from_str::<CacheEntry>(&text)
+            .ok()
+            .filter(|entry| entry.url == url)
+    }
+
+    /// Writes the response to the cache. The cache is only an optimization,
+    /// so a failed write is ignored and the response is fetched next time.
+    fn write(&self, entry: &CacheEntry) {
+        let path = self.entry_path(&entry.url);
+        let part = path.with_extension("json.part");
+        let text = serde_json::to_string(entry).expect("cache entry is always serializable");
+        let _ = fs::create_dir_all(&self.directory)
+            .and_then(|_| fs::write(&part, text))
+            .and_then(|_| fs::rename(&part, &path));
+    }
+
+    /// Answers from the cache and records the age of the response.
+    fn answer(&self, entry: CacheEntry) -> String {
+        let age = entry.age();
+        self.age
+            .set(Some(self.age.get().map_or(age, |old| old.max(age))));
+        entry.body
+    }
+}
+
+impl HttpFetcher for ResponseCache {
+    fn fetch(&self, url: &str) -> Result<String, DriverCheckError> {
+        let cached = self.read(url);
+        match cached {
+            Some(entry) if self.offline || entry.age() < self.ttl => return Ok(self.answer(entry)),
+            None if self.offline => return Err(DriverCheckError::NotCached(url.to_string())),
+            _ => {}
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(entry) = &cached {
+            let validators = [
+                (IF_NONE_MATCH, &entry.etag),
+                (IF_MODIFIED_SINCE, &entry.last_modified),
+            ];
+            for (name, value) in validators {
+                if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                    headers.insert(name, value);
+                }
+            }
+        }
+        let response = HttpClient::shared()
+            .get(url, headers)
+            .and_then(|response| response.error_for_status())
+            .map_err(DriverCheckError::Network)?;
+        if let (StatusCode::NOT_MODIFIED, Some(mut entry)) = (response.status(), cached) {
+            entry.fetched = unix_time();
+            self.write(&entry);
+            return Ok(entry.body);
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let body = response.text().map_err(DriverCheckError::InvalidUtf8)?;
+        self.write(&CacheEntry {
+            url: url.to_string(),
+            fetched: unix_time(),
+            etag,
+            last_modified,
+            body: body.clone(),
+        });
+        Ok(body)
+    }
+}
+
+/// Formats the age roughly in the largest whole unit, e.g. "3 hours".
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    let (count, unit) = match seconds {
+        0..60 => (seconds, "second"),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86400 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    if count == 1 {
+        format!("{count} {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    /// Server answering with an ETag and honouring If-None-Match.
+    fn etag_server() -> TestServer {
+        TestServer::start(|request| match request.header("If-None-Match") {
+            Some("\"v1\"") => Response::status(304),
+            _ => Response::ok("566.14").header("ETag", "\"v1\""),
+        })
+    }
+
+    /// Test that a fresh response is answered without a request.
+    #[test]
+    fn fetch_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = etag_server();
+        let cache = ResponseCache::new(dir.path());
+        assert_eq!(cache.fetch(&server.url).unwrap(), "566.14");
+        assert_eq!(cache.cached_age(), None);
+        assert_eq!(cache.fetch(&server.url).unwrap(), "566.14");
+        assert!(cache.cached_age().unwrap() < Duration::from_secs(5));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    /// Test that an expired response is revalidated with its ETag.
+    #[test]
+    fn fetch_etag() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = etag_server();
+        let cache = ResponseCache::new(dir.path()).ttl(Duration::ZERO);
+        assert_eq!(cache.fetch(&server.url).unwrap(), "566.14");
+        assert_eq!(cache.fetch(&server.url).unwrap(), "566.14");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].header("If-None-Match"), None);
+        assert_eq!(requests[1].header("If-None-Match"), Some("\"v1\""));
+        assert_eq!(cache.cached_age(), None);
+    }
+
+    /// Test that an expired response is revalidated with its modification
+    /// time and replaced when it has changed.
+    #[test]
+    fn fetch_last_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        let modified = "Tue, 19 Nov 2024 14:00:00 GMT";
+        let server = TestServer::start(move |request| match request.header("If-Modified-Since") {
+            Some(since) if since == modified => Response::ok("566.36"),
+            _ => Response::ok("566.14").header("Last-Modified", modified),
+        });
+        let cache = ResponseCache::new(dir.path()).ttl(Duration::ZERO);
+        assert_eq!(cache.fetch(&server.url).unwrap(), "566.14");
+        assert_eq!(cache.fetch(&server.url).unwrap(), "566.36");
+        assert_eq!(
+            server.requests()[1].header("If-Modified-Since"),
+            Some(modified)
+        );
+        let cache = cache.offline(true);
+        assert_eq!(cache.fetch(&server.url).unwrap(), "566.36");
+    }
+
+    /// Test that the offline mode answers from the cache regardless of the
+    /// age and fails without a cached response.
+    #[test]
+    fn fetch_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = etag_server();
+        let cache = ResponseCache::new(dir.path());
+        cache.fetch(&server.url).unwrap();
+
+        let path = cache.entry_path(&server.url);
+        let mut entry: CacheEntry =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        entry.fetched -= 3 * 86400;
+        fs::write(&path, serde_json::to_string(&entry).unwrap()).unwrap();
+
+        let cache = ResponseCache::new(dir.path()).offline(true);
+        assert_eq!(cache.fetch(&server.url).unwrap(), "566.14");
+        assert_eq!(format_age(cache.cached_age().unwrap()), "3 days");
+        assert_eq!(server.requests().len(), 1);
+        assert!(matches!(
+            cache.fetch("https://example.com/missing"),
+            Err(DriverCheckError::NotCached(_))
+        ));
+    }
+
+    /// Test that an error response is not cached.
+    #[test]
+    fn fetch_error_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = TestServer::start(|_| Response::status(404));
+        let cache = ResponseCache::new(dir.path());
+        assert!(cache.fetch(&server.url).is_err());
+        assert!(fs::read_dir(dir.path()).unwrap().next().is_none());
+    }
+
+    /// Test that the age is formatted in the largest unit.
+    #[test]
+    fn format_age_units() {
+        assert_eq!(format_age(Duration::from_secs(1)), "1 second");
+        assert_eq!(format_age(Duration::from_secs(150)), "2 minutes");
+        assert_eq!(format_age(Duration::from_secs(3600)), "1 hour");
+        assert_eq!(format_age(Duration::from_secs(90000)), "1 day");
+    }
+}
@@ -0,0 +1,279 @@
+//! NVIDIA product catalog for resolving product names to the numeric IDs
+//! used by the driver lookup query.
+//!
+//! The catalog is read from the lookupValueSearch service:
+//! <https://www.nvidia.com/Download/API/lookupValueSearch.aspx?TypeID=3>
+//!
+//! A snapshot of the service responses is bundled with the library, so that
+//! names can be resolved also without network access.
+
+use crate::{DriverCheckError, HttpFetcher};
+
+const LOOKUP_URL: &str = r"https://www.nvidia.com/Download/API/lookupValueSearch.aspx";
+
+const BUNDLED_TYPES: &str = include_str!("../data/lookup/types.xml");
+const BUNDLED_SERIES: &str = include_str!("../data/lookup/series.xml");
+const BUNDLED_FAMILIES: &str = include_str!("../data/lookup/families.xml");
+const BUNDLED_OS: &str = include_str!("../data/lookup/os.xml");
+const BUNDLED_LANGUAGES: &str = include_str!("../data/lookup/languages.xml");
+
+/// Kinds of values provided by the lookupValueSearch service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupType {
+    ProductType,
+    ProductSeries,
+    ProductFamily,
+    OperatingSystem,
+    Language,
+}
+
+impl LookupType {
+    /// Returns the lookupValueSearch URL for this kind of values.
+    pub fn url(self) -> String {
+        let type_id = match self {
+            LookupType::ProductType => 1,
+            LookupType::ProductSeries => 2,
+            LookupType::ProductFamily => 3,
+            LookupType::OperatingSystem => 4,
+            LookupType::Language => 5,
+        };
+        format!("{LOOKUP_URL}?TypeID={type_id}")
+    }
+}
+
+/// A single named value, e.g. the product family "GeForce GTX 1070 Ti" with
+/// the value 859. The parent ID links e.g. a product family to its series.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupValue {
+    pub name: String,
+    pub value: u32,
+    pub parent_id: Option<u32>,
+}
+
+/// A product resolved from the catalog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Product {
+    pub name: String,
+    pub series: u32,
+    pub family: u32,
+}
+
+/// Parses a lookupValueSearch XML response.
+pub fn parse_lookup_values(xml: &str) -> Result<Vec<LookupValue>, DriverCheckError> {
+    let document = roxmltree::Document::parse(xml).map_err(DriverCheckError::MalformedXml)?;
+    document
+        .descendants()
+        .filter(|node| node.has_tag_name("LookupValue"))
+        .map(|node| {
+            let child_text = |tag: &str| {
+                node.children()
+                    .find(|child| child.has_tag_name(tag))
+                    .and_then(|child| child.text())
+                    .map(|text| text.split_whitespace().collect::<Vec<&str>>().join(" "))
+            };
+            let name = child_text("Name").ok_or(DriverCheckError::MissingField("name"))?;
+            let value = child_text("Value")
+                .and_then(|value| value.parse().ok())
+                .ok_or(DriverCheckError::MissingField("value"))?;
+            let parent_id = node
+                .attribute("ParentID")
+                .and_then(|parent| parent.trim().parse().ok());
+            Ok(LookupValue {
+                name,
+                value,
+                parent_id,
+            })
+        })
+        .collect()
+}
+
+/// Normalizes a product name for comparison, so that e.g. the name
+/// "NVIDIA GeForce RTX 4070" reported by nvidia-smi matches the catalog
+/// name "GeForce RTX 4070".
+fn normalize_name(name: &str) -> String {
+    let name = name.to_lowercase();
+    let words: Vec<&str> = name.split_whitespace().collect();
+    match words.split_first() {
+        Some((&"nvidia", rest)) if !rest.is_empty() => rest.join(" "),
+        _ => words.join(" "),
+    }
+}
+
+/// Finds the value of the given name from the list.
+fn find_value<'a>(values: &'a [LookupValue], name: &str) -> Option<&'a LookupValue> {
+    let name = normalize_name(name);
+    values
+        .iter()
+        .find(|value| normalize_name(&value.name) == name)
+}
+
+/// Product catalog of the lookupValueSearch service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Catalog {
+    pub product_types: Vec<LookupValue>,
+    pub series: Vec<LookupValue>,
+    pub families: Vec<LookupValue>,
+    pub operating_systems: Vec<LookupValue>,
+    pub languages: Vec<LookupValue>,
+}
+
+impl Catalog {
+    /// Fetches the catalog from the lookupValueSearch service using the
+    /// given fetcher. Just use get_page() here.
+    pub fn fetch(fetcher: &impl HttpFetcher) -> Result<Catalog, DriverCheckError> {
+        let fetch =
+            |lookup_type: LookupType| parse_lookup_values(&fetcher.fetch(&lookup_type.url())?);
+        Ok(Catalog {
+            product_types: fetch(LookupType::ProductType)?,
+            series: fetch(LookupType::ProductSeries)?,
+            families: fetch(LookupType::ProductFamily)?,
+            operating_systems: fetch(LookupType::OperatingSystem)?,
+            languages: fetch(LookupType::Language)?,
+        })
+    }
+
+    /// Returns the catalog snapshot bundled with the library.
+    pub fn bundled() -> Catalog {
+        let parse =
+            |xml: &str| parse_lookup_values(xml).expect("Bundled catalog snapshot is invalid!");
+        Catalog {
+            product_types: parse(BUNDLED_TYPES),
+            series: parse(BUNDLED_SERIES),
+            families: parse(BUNDLED_FAMILIES),
+            operating_systems: parse(BUNDLED_OS),
+            languages: parse(BUNDLED_LANGUAGES),
+        }
+    }
+
+    /// Resolves a product name, e.g. "GeForce RTX 4070" or the name reported
+    /// by nvidia-smi, to its series and family IDs. The comparison ignores
+    /// case, extra whitespace and a leading "NVIDIA".
+    pub fn find_product(&self, name: &str) -> Option<Product> {
+        let name = normalize_name(name);
+        self.families
+            .iter()
+            .filter(|family| normalize_name(&family.name) == name)
+            .find_map(|family| {
+                let series = family.parent_id?;
+                self.series
+                    .iter()
+                    .any(|value| value.value == series)
+                    .then(|| Product {
+                        name: family.name.clone(),
+                        series,
+                        family: family.value,
+                    })
+            })
+    }
+
+    /// Resolves an operating system name, e.g. "Windows 11", to its ID.
+    pub fn find_os(&self, name: &str) -> Option<u32> {
+        find_value(&self.operating_systems, name).map(|value| value.value)
+    }
+
+    /// Resolves a language name, e.g. "English (US)", to its code.
+    pub fn find_language(&self, name: &str) -> Option<u32> {
+        find_value(&self.languages, name).map(|value| value.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stub function for unit tests. Serves the saved XML fixtures.
+    fn get_test_page(url: &str) -> Result<String, DriverCheckError> {
+        let xml = match url.rsplit('=').next() {
+            Some("2") => include_str!("../fixtures/lookup_series.xml"),
+            Some("3") => include_str!("../fixtures/lookup_families.xml"),
+            _ => "<LookupValueSearch><LookupValues /></LookupValueSearch>",
+        };
+        Ok(xml.to_string())
+    }
+
+    /// Test that the lookup URL has the correct type ID.
+    #[test]
+    fn lookup_type_url() {
+        assert_eq!(
+            LookupType::ProductFamily.url(),
+            "https://www.nvidia.com/Download/API/lookupValueSearch.aspx?TypeID=3"
+        );
+    }
+
+    /// Test that the lookup values are parsed from a saved response.
+    #[test]
+    fn parse_lookup_values_success() {
+        let values = parse_lookup_values(include_str!("../fixtures/lookup_series.xml")).unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(
+            values[0],
+            LookupValue {
+                name: "GeForce RTX 40 Series".to_string(),
+                value: 127,
+                parent_id: Some(1),
+            }
+        );
+    }
+
+    /// Test that whitespace around a name is removed.
+    #[test]
+    fn parse_lookup_values_whitespace() {
+        let values = parse_lookup_values(include_str!("../fixtures/lookup_families.xml")).unwrap();
+        assert_eq!(values[2].name, "GeForce GTX 1070 Ti");
+    }
+
+    /// Test that a value without an ID is reported.
+    #[test]
+    fn parse_lookup_values_missing_value() {
+        assert!(matches!(
+            parse_lookup_values(include_str!("../fixtures/lookup_missing_value.xml")),
+            Err(DriverCheckError::MissingField("value"))
+        ));
+    }
+
+    /// Test that invalid XML is reported.
+    #[test]
+    fn parse_lookup_values_malformed() {
+        assert!(matches!(
+            parse_lookup_values("<LookupValueSearch>"),
+            Err(DriverCheckError::MalformedXml(_))
+        ));
+    }
+
+    /// Test that product names are resolved from a fetched catalog.
+    #[test]
+    fn catalog_fetch_find_product() {
+        let catalog = Catalog::fetch(&get_test_page).unwrap();
+        assert_eq!(
+            catalog.find_product("GeForce RTX 4070"),
+            Some(Product {
+                name: "NVIDIA GeForce RTX 4070".to_string(),
+                series: 127,
+                family: 1015,
+            })
+        );
+        assert_eq!(
+            catalog
+                .find_product("NVIDIA GeForce RTX 4070 Laptop GPU")
+                .map(|product| product.series),
+            Some(129)
+        );
+        assert_eq!(catalog.find_product("GeForce RTX 9999"), None);
+    }
+
+    /// Test that the bundled snapshot is valid and resolves the default card.
+    #[test]
+    fn catalog_bundled() {
+        let catalog = Catalog::bundled();
+        let product = catalog.find_product("nvidia geforce gtx 1070 ti").unwrap();
+        assert_eq!((product.series, product.family), (101, 859));
+        assert_eq!(
+            crate::DriverQuery::new().product(&product),
+            crate::DriverQuery::new()
+        );
+        assert_eq!(catalog.find_os("Windows 10 64-bit"), Some(57));
+        assert_eq!(catalog.find_os("windows 11"), Some(135));
+        assert_eq!(catalog.find_language("English (US)"), Some(1033));
+        assert_eq!(catalog.find_language("Klingon"), None);
+    }
+}
@@ -0,0 +1,517 @@
+//! User configuration file.
+//!
+//! The configuration is read from "geforcedrvchk3/config.toml" in the per-user
+//! configuration directory, e.g. "%APPDATA%\geforcedrvchk3\config.toml" under
+//! Windows and "~/.config/geforcedrvchk3/config.toml" under Linux. Every
+//! setting is optional.
+
+use crate::{
+    default_cache_dir, parse_sha256, Catalog, DriverChannel, DriverCheckError, DriverQuery,
+    DriverVersion, HttpConfig, ResponseCache, DEFAULT_CACHE_TTL,
+};
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the configuration file in the configuration directory.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Settings read from the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Name or path of the nvidia-smi executable.
+    pub smi: Option<String>,
+    /// Driver channel, by default the channel of the installed driver.
+    pub channel: Option<DriverChannel>,
+    /// Look for beta and hotfix drivers too.
+    pub beta: bool,
+    /// Directory the drivers are downloaded to.
+    pub download_dir: Option<PathBuf>,
+    /// Base URL or directory of a local driver mirror.
+    pub mirror: Option<String>,
+    /// Install the downloaded driver unattended.
+    pub install: bool,
+    /// Installer switches replacing the default ones.
+    pub install_args: Option<Vec<String>>,
+    /// Expected SHA-256 checksums of the installers by driver version.
+    pub checksums: BTreeMap<String, String>,
+    pub query: QueryConfig,
+    pub notifications: NotificationConfig,
+    pub cache: CacheConfig,
+    pub network: NetworkConfig,
+    pub proxy: ProxyConfig,
+}
+
+/// Parameters of the driver lookup, overriding the detected ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryConfig {
+    /// Product name as in the catalog, e.g. "GeForce RTX 4070".
+    pub product: Option<String>,
+    pub product_series: Option<u32>,
+    pub product_family: Option<u32>,
+    /// Operating system ID or name, e.g. 57 or "Windows 10 64-bit".
+    pub os: Option<LookupId>,
+    /// Language code or name, e.g. 1033 or "English (US)".
+    pub language: Option<LookupId>,
+    pub dch: Option<bool>,
+    pub whql: Option<bool>,
+    /// Number of releases looked up.
+    pub results: Option<u32>,
+}
+
+/// A lookup value given either by its ID or by its name in the catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum LookupId {
+    Id(u32),
+    Name(String),
+}
+
+/// How the user is notified and asked.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Print only the errors.
+    pub quiet: bool,
+    /// Wait for Enter after an error.
+    pub pause: bool,
+    /// Default answer when a new driver is available.
+    pub default_action: DefaultAction,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig {
+            quiet: false,
+            pause: true,
+            default_action: DefaultAction::Download,
+        }
+    }
+}
+
+/// Default answer to the question what to do with a new driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DefaultAction {
+    #[default]
+    Download,
+    OpenBrowser,
+    Quit,
+}
+
+/// On-disk cache of the driver lookup responses.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Cache the responses. The cache is used in offline mode regardless.
+    pub enabled: bool,
+    /// Directory of the cache, by default "geforcedrvchk3" in the per-user
+    /// cache directory.
+    pub directory: Option<PathBuf>,
+    /// How long a response is used without revalidating it, in seconds.
+    pub ttl: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            directory: None,
+            ttl: DEFAULT_CACHE_TTL.as_secs(),
+        }
+    }
+}
+
+/// Timeouts and retries of the requests to the online resources. The
+/// defaults of `HttpConfig` are used for the missing settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Timeout for connecting to the server in seconds.
+    pub connect_timeout: Option<u64>,
+    /// Timeout for the response and the reads of the response in seconds.
+    pub timeout: Option<u64>,
+    /// Number of times a failed request is retried.
+    pub retries: Option<u32>,
+    pub user_agent: Option<String>,
+    /// PEM file with root certificates trusted in addition to the system ones.
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// Proxy used for the online resources.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// Proxy URL for both HTTP and HTTPS, e.g. "http://proxy.example.com:8080".
+    pub url: Option<String>,
+    /// Proxy URL for HTTP, overriding `url`.
+    pub http: Option<String>,
+    /// Proxy URL for HTTPS, overriding `url`.
+    pub https: Option<String>,
+    /// Hosts accessed without the proxy, e.g. "localhost" or ".example.com".
+    pub no_proxy: Vec<String>,
+}
+
+/// Returns the path of the configuration file in the per-user configuration
+/// directory, if the directory is known.
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("geforcedrvchk3").join(CONFIG_FILE))
+}
+
+impl Config {
+    /// Parses and validates the configuration. The path is only used in the
+    /// error messages.
+    pub fn parse(text: &str, path: &Path) -> Result<Config, DriverCheckError> {
+        let invalid = |message: String| DriverCheckError::Config {
+            path: path.to_path_buf(),
+            message,
+        };
+        let config: Config = toml::from_str(text).map_err(|err| invalid(err.to_string()))?;
+        config.validate().map_err(invalid)?;
+        Ok(config)
+    }
+
+    /// Loads the configuration file. Without an explicit path the file is
+    /// looked up from the per-user configuration directory, and the default
+    /// configuration is used if there is no such file.
+    pub fn load(path: Option<&Path>) -> Result<Config, DriverCheckError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_config_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text, &path),
+            Err(err) if !required && err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(source) => Err(DriverCheckError::ConfigFile { path, source }),
+        }
+    }
+
+    /// Checks the values that the TOML parser cannot check. The message
+    /// starts with the offending key.
+    fn validate(&self) -> Result<(), String> {
+        let catalog = Catalog::bundled();
+        if let Some(product) = &self.query.product {
+            catalog
+                .find_product(product)
+                .ok_or_else(|| format!("query.product: unknown product '{product}'"))?;
+        }
+        if let Some(LookupId::Name(name)) = &self.query.os {
+            catalog
+                .find_os(name)
+                .ok_or_else(|| format!("query.os: unknown operating system '{name}'"))?;
+        }
+        if let Some(LookupId::Name(name)) = &self.query.language {
+            catalog
+                .find_language(name)
+                .ok_or_else(|| format!("query.language: unknown language '{name}'"))?;
+        }
+        if self.query.results == Some(0) {
+            return Err("query.results: must be at least 1".to_string());
+        }
+        if let Some(smi) = &self.smi {
+            if smi.trim().is_empty() {
+                return Err("smi: must not be empty".to_string());
+            }
+        }
+        if let Some(mirror) = &self.mirror {
+            if mirror.trim().is_empty() {
+                return Err("mirror: must not be empty".to_string());
+            }
+        }
+        for (version, sha256) in &self.checksums {
+            version
+                .parse::<DriverVersion>()
+                .map_err(|err| format!("checksums.\"{version}\": {err}"))?;
+            parse_sha256(sha256).map_err(|err| format!("checksums.\"{version}\": {err}"))?;
+        }
+        let proxies = [
+            ("url", &self.proxy.url),
+            ("http", &self.proxy.http),
+            ("https", &self.proxy.https),
+        ];
+        for (key, url) in proxies {
+            if let Some(url) = url {
+                Url::parse(url)
+                    .map_err(|err| format!("proxy.{key}: invalid URL '{url}': {err}"))?;
+            }
+        }
+        let network = &self.network;
+        if network.connect_timeout == Some(0) {
+            return Err("network.connect_timeout: must be at least 1".to_string());
+        }
+        if network.timeout == Some(0) {
+            return Err("network.timeout: must be at least 1".to_string());
+        }
+        if let Some(user_agent) = &network.user_agent {
+            if user_agent.trim().is_empty() || user_agent.contains(char::is_control) {
+                return Err(format!(
+                    "network.user_agent: invalid User-Agent '{user_agent}'"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the settings of the HTTP client.
+    pub fn http_config(&self) -> HttpConfig {
+        let network = &self.network;
+        let mut config = HttpConfig::new().no_proxy(self.proxy.no_proxy.iter().cloned());
+        config.proxy = self.proxy.url.clone();
+        config.http_proxy = self.proxy.http.clone();
+        config.https_proxy = self.proxy.https.clone();
+        if let Some(seconds) = network.connect_timeout {
+            config = config.connect_timeout(Duration::from_secs(seconds));
+        }
+        if let Some(seconds) = network.timeout {
+            config = config.timeout(Duration::from_secs(seconds));
+        }
+        if let Some(retries) = network.retries {
+            config.retries = retries;
+        }
+        if let Some(user_agent) = &network.user_agent {
+            config = config.user_agent(user_agent);
+        }
+        if let Some(path) = &network.ca_bundle {
+            config = config.ca_bundle(path);
+        }
+        config
+    }
+
+    /// Applies the configured lookup parameters to the query.
+    pub fn apply_query(&self, mut query: DriverQuery) -> DriverQuery {
+        let catalog = Catalog::bundled();
+        let settings = &self.query;
+        if let Some(product) = settings.product.as_deref() {
+            if let Some(product) = catalog.find_product(product) {
+                query = query.product(&product);
+            }
+        }
+        if let Some(series) = settings.product_series {
+            query = query.product_series(series);
+        }
+        if let Some(family) = settings.product_family {
+            query = query.product_family(family);
+        }
+        match &settings.os {
+            Some(LookupId::Id(id)) => query = query.os(*id),
+            Some(LookupId::Name(name)) => {
+                if let Some(id) = catalog.find_os(name) {
+                    query = query.os(id);
+                }
+            }
+            None => {}
+        }
+        match &settings.language {
+            Some(LookupId::Id(code)) => query = query.language(*code),
+            Some(LookupId::Name(name)) => {
+                if let Some(code) = catalog.find_language(name) {
+                    query = query.language(code);
+                }
+            }
+            None => {}
+        }
+        if let Some(dch) = settings.dch {
+            query = query.dch(dch);
+        }
+        if let Some(whql) = settings.whql {
+            query = query.whql(whql);
+        }
+        if let Some(results) = settings.results {
+            query = query.number_of_results(results);
+        }
+        if let Some(channel) = self.channel {
+            query = query.channel(channel);
+        }
+        query
+    }
+
+    /// Returns the response cache, or `None` if the cache is disabled or
+    /// there is no cache directory. The offline mode uses the cache even if
+    /// it is disabled.
+    pub fn response_cache(&self, offline: bool) -> Option<ResponseCache> {
+        if !self.cache.enabled && !offline {
+            return None;
+        }
+        let directory = self.cache.directory.clone().or_else(default_cache_dir)?;
+        Some(
+            ResponseCache::new(directory)
+                .ttl(Duration::from_secs(self.cache.ttl))
+                .offline(offline),
+        )
+    }
+
+    /// Returns the configured checksum of the installer of the version.
+    pub fn checksum(&self, version: &DriverVersion) -> Option<&str> {
+        self.checksums
+            .iter()
+            .find(|(key, _)| key.parse::<DriverVersion>().ok().as_ref() == Some(version))
+            .map(|(_, sha256)| sha256.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the error message of an invalid configuration.
+    fn error_message(text: &str) -> String {
+        match Config::parse(text, Path::new("config.toml")) {
+            Err(DriverCheckError::Config { message, .. }) => message,
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    /// Test that every setting is read.
+    #[test]
+    fn config_parse_success() {
+        let config = Config::parse(
+            include_str!("../fixtures/config.toml"),
+            Path::new("config.toml"),
+        )
+        .unwrap();
+        assert_eq!(config.smi.as_deref(), Some("/opt/nvidia/bin/nvidia-smi"));
+        assert_eq!(config.channel, Some(DriverChannel::Studio));
+        assert!(config.beta);
+        assert_eq!(
+            config.download_dir,
+            Some(PathBuf::from("/var/cache/drivers"))
+        );
+        assert_eq!(
+            config.install_args,
+            Some(vec!["-s".to_string(), "-noreboot".to_string()])
+        );
+        assert_eq!(config.query.os, Some(LookupId::Name("Windows 11".into())));
+        assert_eq!(config.query.language, Some(LookupId::Id(1033)));
+        assert!(!config.notifications.pause);
+        assert_eq!(
+            config.notifications.default_action,
+            DefaultAction::OpenBrowser
+        );
+        assert_eq!(config.proxy.no_proxy, ["localhost", ".example.com"]);
+        assert!(config
+            .checksum(&DriverVersion::new(566, 14))
+            .unwrap()
+            .starts_with("b94d27b9"));
+
+        let query = config.apply_query(DriverQuery::new());
+        assert_eq!(query.os, 135);
+        assert_eq!(query.language, 1033);
+        assert_eq!(query.channel, DriverChannel::Studio);
+        assert_eq!(query.number_of_results, 20);
+    }
+
+    /// Test that the network and proxy settings are given to the HTTP client.
+    #[test]
+    fn config_http_config() {
+        let config = Config::parse(
+            include_str!("../fixtures/config.toml"),
+            Path::new("config.toml"),
+        )
+        .unwrap();
+        let http = config.http_config();
+        assert_eq!(http.connect_timeout, Duration::from_secs(5));
+        assert_eq!(http.timeout, Duration::from_secs(60));
+        assert_eq!(http.retries, 4);
+        assert_eq!(http.user_agent, "drivercheck/1.0");
+        assert_eq!(
+            http.ca_bundle,
+            Some(PathBuf::from("/etc/ssl/corporate-ca.pem"))
+        );
+        assert_eq!(http.proxy.as_deref(), Some("http://proxy.example.com:8080"));
+        assert_eq!(http.http_proxy, None);
+        assert_eq!(
+            http.https_proxy.as_deref(),
+            Some("http://secure-proxy.example.com:8443")
+        );
+        assert_eq!(http.no_proxy, ["localhost", ".example.com"]);
+        assert_eq!(Config::default().http_config(), HttpConfig::default());
+    }
+
+    /// Test that the disabled cache is used only in offline mode.
+    #[test]
+    fn config_response_cache() {
+        let config = Config::parse(
+            include_str!("../fixtures/config.toml"),
+            Path::new("config.toml"),
+        )
+        .unwrap();
+        assert_eq!(config.cache.ttl, 600);
+        assert!(config.response_cache(false).is_none());
+        let cache = config.response_cache(true).unwrap();
+        assert_eq!(cache.directory(), Path::new("/var/cache/geforcedrvchk3"));
+
+        let config =
+            Config::parse("[cache]\ndirectory = \"cache\"\n", Path::new("config.toml")).unwrap();
+        assert_eq!(config.cache.ttl, DEFAULT_CACHE_TTL.as_secs());
+        assert!(config.response_cache(false).is_some());
+    }
+
+    /// Test that an empty configuration has the defaults.
+    #[test]
+    fn config_parse_empty() {
+        let config = Config::parse("", Path::new("config.toml")).unwrap();
+        assert_eq!(config, Config::default());
+        assert!(config.notifications.pause);
+        assert_eq!(
+            config.apply_query(DriverQuery::new()).url(),
+            DriverQuery::new().url()
+        );
+    }
+
+    /// Test that the errors point at the offending key.
+    #[test]
+    fn config_parse_invalid() {
+        let message = error_message("[query]\nlanguag = 1033\n");
+        assert!(message.contains("line 2"), "{message}");
+        assert!(message.contains("unknown field `languag`"), "{message}");
+
+        let message = error_message("beta = true\nchannel = \"weekly\"\n");
+        assert!(message.contains("line 2"), "{message}");
+        assert!(message.contains("channel = \"weekly\""), "{message}");
+
+        let message = error_message("[notifications]\ndefault_action = \"install\"\n");
+        assert!(message.contains("default_action"), "{message}");
+
+        let message = error_message("[query]\nos = \"Windows 95\"\n");
+        assert!(message.starts_with("query.os:"), "{message}");
+
+        let message = error_message("[query]\nresults = 0\n");
+        assert!(message.starts_with("query.results:"), "{message}");
+
+        let message = error_message("[checksums]\n\"566.14\" = \"abc\"\n");
+        assert!(message.starts_with("checksums.\"566.14\":"), "{message}");
+
+        let message = error_message("[proxy]\nurl = \"not a url\"\n");
+        assert!(message.starts_with("proxy.url:"), "{message}");
+
+        let message = error_message("[proxy]\nhttps = \"http://\"\n");
+        assert!(message.starts_with("proxy.https:"), "{message}");
+
+        let message = error_message("[network]\ntimeout = 0\n");
+        assert!(message.starts_with("network.timeout:"), "{message}");
+
+        let message = error_message("[network]\nuser_agent = \"a\\nb\"\n");
+        assert!(message.starts_with("network.user_agent:"), "{message}");
+    }
+
+    /// Test that a missing file is an error only if it was asked for.
+    #[test]
+    fn config_load_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = Config::load(Some(&dir.path().join("missing.toml")));
+        assert!(matches!(result, Err(DriverCheckError::ConfigFile { .. })));
+
+        let path = dir.path().join(CONFIG_FILE);
+        fs::write(&path, "install = true\n").unwrap();
+        assert!(Config::load(Some(&path)).unwrap().install);
+    }
+}
@@ -0,0 +1,382 @@
+//! Downloading of the driver installation packages.
+//!
+//! The package is first written to a temporary ".part" file next to the
+//! final file, which is renamed only after the whole package is received.
+//! An interrupted download is resumed from the ".part" file with an HTTP
+//! Range request. Installers on a local or network file system, e.g. on a
+//! mirror share, are copied the same way.
+
+use crate::{format_file_size, DriverCheckError, HttpClient};
+use reqwest::blocking::Response;
+use reqwest::header::{HeaderMap, HeaderValue, RANGE};
+use reqwest::{StatusCode, Url};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Width of the progress bar in characters.
+const BAR_WIDTH: usize = 30;
+
+/// Progress of a download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// Number of bytes on disk so far, including a resumed part.
+    pub downloaded: u64,
+    /// Size of the whole file, if the server told it.
+    pub total: Option<u64>,
+}
+
+impl DownloadProgress {
+    /// Returns the completed percentage, if the size of the file is known.
+    pub fn percent(&self) -> Option<u8> {
+        self.total.map(|total| match total {
+            0 => 100,
+            _ => (self.downloaded.min(total) * 100 / total) as u8,
+        })
+    }
+}
+
+/// Displays the progress as a progress bar, e.g.
+/// "[###############               ]  50% 338.59 MB / 677.17 MB".
+impl fmt::Display for DownloadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.percent(), self.total) {
+            (Some(percent), Some(total)) => {
+                let filled = BAR_WIDTH * percent as usize / 100;
+                write!(
+                    f,
+                    "[{}{}] {percent:>3}% {} / {}",
+                    "#".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    format_file_size(self.downloaded),
+                    format_file_size(total)
+                )
+            }
+            _ => write!(f, "{}", format_file_size(self.downloaded)),
+        }
+    }
+}
+
+/// Returns the name of the downloaded file for the URL, i.e. the last
+/// segment of the path, e.g. "566.14-desktop-win10-win11-64bit-international-dch-whql.exe".
+pub fn download_file_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    match path.rsplit(['/', '\\']).next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => "driver.exe".to_string(),
+    }
+}
+
+/// Returns the path of a "file:" URL or of a plain path, e.g. an installer
+/// on a mirror share. Returns `None` for the other URLs.
+fn local_path(url: &str) -> Option<PathBuf> {
+    match Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "file" => parsed.to_file_path().ok(),
+        // A Windows path, e.g. "D:\Drivers\566.14-win11.exe".
+        Ok(parsed) if parsed.scheme().len() == 1 => Some(PathBuf::from(url)),
+        Ok(_) => None,
+        Err(_) => Some(PathBuf::from(url)),
+    }
+}
+
+/// Sends the request for the file, resuming from the part file if there is
+/// one. Returns the response and the offset the response starts from.
+fn request_file(url: &str, part: &Path) -> Result<(Response, u64), DriverCheckError> {
+    let mut offset = fs::metadata(part).map(|meta| meta.len()).unwrap_or(0);
+    let client = HttpClient::shared();
+    let mut headers = HeaderMap::new();
+    if offset > 0 {
+        let range = HeaderValue::from_str(&format!("bytes={offset}-")).expect("valid range");
+        headers.insert(RANGE, range);
+    }
+    let mut response = client
+        .get(url, headers)
+        .map_err(DriverCheckError::Download)?;
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The part file does not match the file on the server, start over.
+        offset = 0;
+        response = client
+            .get(url, HeaderMap::new())
+            .map_err(DriverCheckError::Download)?;
+    }
+    let response = response
+        .error_for_status()
+        .map_err(DriverCheckError::Download)?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        // The server sends the whole file, if it does not support ranges.
+        offset = 0;
+    }
+    Ok((response, offset))
+}
+
+/// Downloads the file from the URL into the directory and returns the path
+/// of the downloaded file. An existing file with the same name is replaced.
+///
+/// The progress callback is called every time a chunk has been written. The
+/// file is downloaded with the shared `HttpClient`. A "file:" URL or a plain
+/// path is copied instead.
+pub fn download_file(
+    url: &str,
+    directory: &Path,
+    mut progress: impl FnMut(DownloadProgress),
+) -> Result<PathBuf, DriverCheckError> {
+    let path = directory.join(download_file_name(url));
+    let part = directory.join(format!("{}.part", download_file_name(url)));
+    let file_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source: io::Error| DriverCheckError::DownloadFile {
+            path: path.clone(),
+            source,
+        }
+    };
+
+    let source = local_path(url);
+    let (mut reader, offset, total): (Box<dyn Read>, u64, Option<u64>) = match &source {
+        Some(source) => {
+            let file = File::open(source).map_err(file_error(source))?;
+            let total = file.metadata().map(|meta| meta.len()).ok();
+            (Box::new(file), 0, total)
+        }
+        None => {
+            let (response, offset) = request_file(url, &part)?;
+            let total = response.content_length().map(|length| offset + length);
+            (Box::new(response), offset, total)
+        }
+    };
+
+    let mut file = if offset > 0 {
+        OpenOptions::new().append(true).open(&part)
+    } else {
+        File::create(&part)
+    }
+    .map_err(file_error(&part))?;
+    let mut downloaded = offset;
+    let mut buffer = [0; 64 * 1024];
+    progress(DownloadProgress { downloaded, total });
+    loop {
+        let count = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            // A failed read is a file error for a local file, but a network
+            // error for a response.
+            Err(err) => {
+                return Err(match &source {
+                    Some(source) => file_error(source)(err),
+                    None => DriverCheckError::DownloadInterrupted(err),
+                })
+            }
+        };
+        file.write_all(&buffer[..count])
+            .map_err(file_error(&part))?;
+        downloaded += count as u64;
+        progress(DownloadProgress { downloaded, total });
+    }
+    file.sync_all().map_err(file_error(&part))?;
+    drop(file);
+    fs::rename(&part, &path).map_err(file_error(&path))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Contents of the test driver package.
+    fn package() -> Vec<u8> {
+        (0..200_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Starts a server serving the test package, honouring Range requests
+    /// if `ranges` is set.
+    fn package_server(ranges: bool) -> TestServer {
+        TestServer::start(move |request| {
+            let body = package();
+            if request.path != "/drivers/566.14.exe" {
+                return Response::status(404);
+            }
+            let start = request
+                .header("Range")
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+            match start {
+                Some(start) if ranges && start >= body.len() => Response::status(416),
+                Some(start) if ranges => {
+                    let range = format!("bytes {start}-{}/{}", body.len() - 1, body.len());
+                    let mut response = Response::ok(&body[start..]).header("Content-Range", &range);
+                    response.status = 206;
+                    response
+                }
+                _ => Response::ok(body),
+            }
+        })
+    }
+
+    /// Test that the file name is taken from the URL.
+    #[test]
+    fn download_file_name_success() {
+        assert_eq!(
+            download_file_name("https://us.download.nvidia.com/Windows/566.14/566.14-win11.exe"),
+            "566.14-win11.exe"
+        );
+        assert_eq!(
+            download_file_name("http://localhost/driver.run?token=1#top"),
+            "driver.run"
+        );
+        assert_eq!(download_file_name("http://localhost/"), "driver.exe");
+    }
+
+    /// Test that the progress is displayed as a bar.
+    #[test]
+    fn download_progress_display() {
+        let progress = DownloadProgress {
+            downloaded: 355_032_105,
+            total: Some(710_064_210),
+        };
+        assert_eq!(progress.percent(), Some(50));
+        assert_eq!(
+            progress.to_string(),
+            "[###############               ]  50% 338.59 MB / 677.17 MB"
+        );
+        let progress = DownloadProgress {
+            downloaded: 1 << 20,
+            total: None,
+        };
+        assert_eq!(progress.percent(), None);
+        assert_eq!(progress.to_string(), "1.00 MB");
+    }
+
+    /// Test that the whole file is downloaded and renamed.
+    #[test]
+    fn download_file_success() {
+        let server = package_server(true);
+        let dir = tempfile::tempdir().unwrap();
+        let mut last = None;
+        let url = format!("{}drivers/566.14.exe", server.url);
+        let path = download_file(&url, dir.path(), |progress| last = Some(progress)).unwrap();
+        assert_eq!(path, dir.path().join("566.14.exe"));
+        assert_eq!(fs::read(&path).unwrap(), package());
+        assert!(!dir.path().join("566.14.exe.part").exists());
+        assert_eq!(
+            last,
+            Some(DownloadProgress {
+                downloaded: 200_000,
+                total: Some(200_000)
+            })
+        );
+        assert_eq!(server.requests()[0].header("Range"), None);
+    }
+
+    /// Test that a partial download is resumed with a Range request.
+    #[test]
+    fn download_file_resume() {
+        let server = package_server(true);
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("566.14.exe.part"), &package()[..70_000]).unwrap();
+        let mut first = None;
+        let url = format!("{}drivers/566.14.exe", server.url);
+        let path = download_file(&url, dir.path(), |progress| {
+            first.get_or_insert(progress);
+        })
+        .unwrap();
+        assert_eq!(fs::read(path).unwrap(), package());
+        assert_eq!(server.requests()[0].header("Range"), Some("bytes=70000-"));
+        assert_eq!(
+            first,
+            Some(DownloadProgress {
+                downloaded: 70_000,
+                total: Some(200_000)
+            })
+        );
+    }
+
+    /// Test that the download starts over if the server ignores the range.
+    #[test]
+    fn download_file_range_ignored() {
+        let server = package_server(false);
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("566.14.exe.part"), b"garbage").unwrap();
+        let url = format!("{}drivers/566.14.exe", server.url);
+        let path = download_file(&url, dir.path(), |_| {}).unwrap();
+        assert_eq!(fs::read(path).unwrap(), package());
+    }
+
+    /// Test that the download starts over if the part file is too long.
+    #[test]
+    fn download_file_range_not_satisfiable() {
+        let server = package_server(true);
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("566.14.exe.part"), vec![0; 300_000]).unwrap();
+        let url = format!("{}drivers/566.14.exe", server.url);
+        let path = download_file(&url, dir.path(), |_| {}).unwrap();
+        assert_eq!(fs::read(path).unwrap(), package());
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    /// Test that a local file is copied, given either as a path or as a
+    /// "file:" URL.
+    #[test]
+    fn download_file_local() {
+        let mirror = tempfile::tempdir().unwrap();
+        let source = mirror.path().join("566.14.exe");
+        fs::write(&source, package()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut last = None;
+        let path = download_file(source.to_str().unwrap(), dir.path(), |progress| {
+            last = Some(progress)
+        })
+        .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), package());
+        assert_eq!(last.unwrap().total, Some(200_000));
+
+        let url = Url::from_file_path(&source).unwrap();
+        fs::remove_file(&path).unwrap();
+        let path = download_file(url.as_str(), dir.path(), |_| {}).unwrap();
+        assert_eq!(path, dir.path().join("566.14.exe"));
+        assert_eq!(fs::read(&path).unwrap(), package());
+
+        let result = download_file(
+            mirror.path().join("missing.exe").to_str().unwrap(),
+            dir.path(),
+            |_| {},
+        );
+        assert!(matches!(result, Err(DriverCheckError::DownloadFile { .. })));
+    }
+
+    /// Test that a connection closed in the middle of the download is a
+    /// download error, not a file error.
+    #[test]
+    fn download_file_interrupted() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/566.14.exe", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 200000\r\n\r\npartial");
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let result = download_file(&url, dir.path(), |_| {});
+        assert!(
+            matches!(result, Err(DriverCheckError::DownloadInterrupted(_))),
+            "{result:?}"
+        );
+        assert!(!dir.path().join("566.14.exe").exists());
+    }
+
+    /// Test that an HTTP error leaves no file behind.
+    #[test]
+    fn download_file_not_found() {
+        let server = package_server(true);
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("{}drivers/missing.exe", server.url);
+        let result = download_file(&url, dir.path(), |_| {});
+        assert!(matches!(result, Err(DriverCheckError::Download(_))));
+        assert!(!dir.path().join("missing.exe").exists());
+        assert!(!dir.path().join("missing.exe.part").exists());
+    }
+}
@@ -0,0 +1,198 @@
+//! Error type shared by all the functions of this library.
+
+use crate::DriverVersion;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Reasons why checking the driver versions can fail.
+#[derive(Debug, Error)]
+pub enum DriverCheckError {
+    /// The online resource could not be reached.
+    #[error("Unable to access the online resources!")]
+    Network(#[source] reqwest::Error),
+
+    /// The online resource returned something that is not valid text.
+    #[error("The page has invalid UTF-8 characters!")]
+    InvalidUtf8(#[source] reqwest::Error),
+
+    /// The online resource returned something that is not valid JSON.
+    #[error("Incorrect information at the online resource!")]
+    MalformedJson(#[source] json::Error),
+
+    /// The online resource returned something that is not valid XML.
+    #[error("Incorrect information at the online resource!")]
+    MalformedXml(#[source] roxmltree::Error),
+
+    /// The named field was not found from the online resource.
+    #[error("Cannot find {0} information from the online resource!")]
+    MissingField(&'static str),
+
+    /// The given string is not a valid release date.
+    #[error("Invalid release date: '{0}'")]
+    ParseDate(String),
+
+    /// nvidia-smi could not be found.
+    #[error("Couldn't detect location for nvidia-smi. Maybe the driver is not installed?")]
+    SmiNotFound,
+
+    /// nvidia-smi could not be executed.
+    #[error("Couldn't detect installed version. Maybe the driver is not installed?")]
+    SmiFailed(#[source] io::Error),
+
+    /// nvidia-smi output did not contain the expected information.
+    #[error("Cannot find installed version information!")]
+    SmiOutput,
+
+    /// The Linux kernel module information file could not be read.
+    #[error("Couldn't read the driver version file. Maybe the driver is not loaded?")]
+    DriverFile(#[source] io::Error),
+
+    /// The web browser could not be started.
+    #[error("Couldn't start the web browser!")]
+    BrowserLaunch(#[source] io::Error),
+
+    /// The given string is not a known driver channel.
+    #[error("Unknown driver channel: '{0}'")]
+    UnknownChannel(String),
+
+    /// The given string is not a product of the catalog.
+    #[error("Unknown product: '{0}'")]
+    UnknownProduct(String),
+
+    /// The driver installation package could not be downloaded.
+    #[error("Unable to download the driver!")]
+    Download(#[source] reqwest::Error),
+
+    /// The connection failed while the driver installation package was
+    /// being received.
+    #[error("The driver download was interrupted!")]
+    DownloadInterrupted(#[source] io::Error),
+
+    /// The downloaded driver installation package could not be written.
+    #[error("Couldn't write the downloaded driver to {}!", .path.display())]
+    DownloadFile {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The driver installation package could not be read.
+    #[error("Couldn't read the driver installer {}!", .path.display())]
+    InstallerFile {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The size of the driver installation package is not the expected one.
+    #[error(
+        "Size of {} is {actual} bytes, but {expected} bytes were expected!",
+        .path.display()
+    )]
+    SizeMismatch {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+
+    /// The checksum of the driver installation package is not the expected one.
+    #[error(
+        "SHA-256 checksum of {} is {actual}, but {expected} was expected!",
+        .path.display()
+    )]
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+
+    /// The given string is not a valid SHA-256 checksum.
+    #[error("Invalid SHA-256 checksum: '{0}'")]
+    InvalidChecksum(String),
+
+    /// The driver installer could not be started.
+    #[error("Couldn't run the driver installer {}!", .path.display())]
+    InstallerLaunch {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The driver installer exited with a non-zero exit code.
+    #[error("The driver installer failed with exit code {0}!")]
+    InstallerExit(i32),
+
+    /// The output of the driver installer could not be written to the log.
+    #[error("Couldn't write the installer log to {}!", .path.display())]
+    InstallLog {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Some other driver version than the installed one is in use.
+    #[error("Driver version {installed} is in use after the installation, expected {expected}!")]
+    InstallNotConfirmed {
+        expected: DriverVersion,
+        installed: DriverVersion,
+    },
+
+    /// The configuration file could not be read.
+    #[error("Couldn't read the configuration file {}!", .path.display())]
+    ConfigFile {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The configuration file has an invalid setting.
+    #[error("Invalid configuration file {}: {message}", .path.display())]
+    Config { path: PathBuf, message: String },
+
+    /// The HTTP client could not be created, e.g. because of an invalid proxy
+    /// URL or certificate.
+    #[error("Invalid HTTP client settings!")]
+    HttpClient(#[source] reqwest::Error),
+
+    /// The given string cannot be sent as the User-Agent header.
+    #[error("Invalid User-Agent: '{0}'")]
+    UserAgent(String),
+
+    /// The file of the trusted root certificates could not be read.
+    #[error("Couldn't read the CA bundle {}!", .path.display())]
+    CaBundle {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The response is not in the cache in offline mode.
+    #[error("No cached response for {0} in offline mode!")]
+    NotCached(String),
+
+    /// The mirror manifest could not be read or written.
+    #[error("Couldn't access the mirror manifest {}!", .path.display())]
+    ManifestFile {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The mirror source is selected, but the location of the mirror is not
+    /// given.
+    #[error("The mirror source needs the location of the mirror, see --mirror.")]
+    MirrorNotSet,
+
+    /// The mirror manifest is not valid.
+    #[error("Incorrect information in the mirror manifest!")]
+    MalformedManifest(#[source] serde_json::Error),
+
+    /// The requested driver version is not among the available releases.
+    #[error("Driver version {0} is not available!")]
+    ReleaseNotFound(DriverVersion),
+
+    /// The given string is not a valid driver version number.
+    #[error("Invalid driver version number: '{0}'")]
+    ParseVersion(String),
+}
@@ -0,0 +1,360 @@
+//! Shared HTTP client used for every online resource.
+//!
+//! The client has timeouts, so that a check never hangs, and retries the
+//! requests failing with a server error or a timeout with an exponential
+//! backoff. The proxy, the user agent and the trusted root certificates can
+//! be configured for corporate networks.
+
+use crate::{DriverCheckError, HttpFetcher, VERSION};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use reqwest::{Certificate, NoProxy, Proxy};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// The client used by `get_page()` and `download_file()`.
+static SHARED: OnceLock<HttpClient> = OnceLock::new();
+
+/// Settings of the HTTP client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpConfig {
+    /// Timeout for connecting to the server.
+    pub connect_timeout: Duration,
+    /// Timeout for the response, and for every read of the response body.
+    pub timeout: Duration,
+    /// Proxy used for both HTTP and HTTPS.
+    pub proxy: Option<String>,
+    /// Proxy used for HTTP, overriding `proxy`.
+    pub http_proxy: Option<String>,
+    /// Proxy used for HTTPS, overriding `proxy`.
+    pub https_proxy: Option<String>,
+    /// Hosts accessed without the proxy, e.g. "localhost" or ".example.com".
+    pub no_proxy: Vec<String>,
+    pub user_agent: String,
+    /// PEM file with root certificates trusted in addition to the system ones.
+    pub ca_bundle: Option<PathBuf>,
+    /// Number of times a failed request is retried.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every further retry.
+    pub retry_delay: Duration,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            proxy: None,
+            http_proxy: None,
+            https_proxy: None,
+            no_proxy: Vec::new(),
+            user_agent: format!("geforcedrvchk3/{VERSION}"),
+            ca_bundle: None,
+            retries: 2,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Creates the default settings.
+    pub fn new() -> HttpConfig {
+        HttpConfig::default()
+    }
+
+    /// Sets the timeout for connecting to the server.
+    pub fn connect_timeout(mut self, timeout: Duration) -> HttpConfig {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout for the response and the reads of the response body.
+    pub fn timeout(mut self, timeout: Duration) -> HttpConfig {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the proxy used for both HTTP and HTTPS.
+    pub fn proxy(mut self, url: &str) -> HttpConfig {
+        self.proxy = Some(url.to_string());
+        self
+    }
+
+    /// Sets the proxy used for HTTP.
+    pub fn http_proxy(mut self, url: &str) -> HttpConfig {
+        self.http_proxy = Some(url.to_string());
+        self
+    }
+
+    /// Sets the proxy used for HTTPS.
+    pub fn https_proxy(mut self, url: &str) -> HttpConfig {
+        self.https_proxy = Some(url.to_string());
+        self
+    }
+
+    /// Sets the hosts accessed without the proxy.
+    pub fn no_proxy<S: Into<String>>(mut self, hosts: impl IntoIterator<Item = S>) -> HttpConfig {
+        self.no_proxy = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the User-Agent header sent with every request.
+    pub fn user_agent(mut self, user_agent: &str) -> HttpConfig {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Sets the PEM file with additional trusted root certificates.
+    pub fn ca_bundle(mut self, path: impl Into<PathBuf>) -> HttpConfig {
+        self.ca_bundle = Some(path.into());
+        self
+    }
+
+    /// Sets the number of retries and the delay before the first retry.
+    pub fn retries(mut self, retries: u32, delay: Duration) -> HttpConfig {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+}
+
+/// HTTP client with timeouts and retries.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl HttpClient {
+    /// Creates a client with the settings. Fails if a proxy URL, the user
+    /// agent or the CA bundle is invalid.
+    pub fn new(config: &HttpConfig) -> Result<HttpClient, DriverCheckError> {
+        let no_proxy = NoProxy::from_string(&config.no_proxy.join(","));
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(&config.user_agent)
+                .map_err(|_| DriverCheckError::UserAgent(config.user_agent.clone()))?,
+        );
+        let mut builder = Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.timeout)
+            .default_headers(headers);
+        let proxies = [
+            config.http_proxy.as_deref().map(Proxy::http),
+            config.https_proxy.as_deref().map(Proxy::https),
+            config.proxy.as_deref().map(Proxy::all),
+        ];
+        for proxy in proxies.into_iter().flatten() {
+            let proxy = proxy.map_err(DriverCheckError::HttpClient)?;
+            builder = builder.proxy(proxy.no_proxy(no_proxy.clone()));
+        }
+        if let Some(path) = &config.ca_bundle {
+            let pem = fs::read(path).map_err(|source| DriverCheckError::CaBundle {
+                path: path.clone(),
+                source,
+            })?;
+            for certificate in
+                Certificate::from_pem_bundle(&pem).map_err(DriverCheckError::HttpClient)?
+            {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        Ok(HttpClient {
+            client: builder.build().map_err(DriverCheckError::HttpClient)?,
+            retries: config.retries,
+            retry_delay: config.retry_delay,
+        })
+    }
+
+    /// Returns the client shared by `get_page()` and `download_file()`. The
+    /// default settings are used, unless another client has been made the
+    /// shared one with `make_shared()`.
+    pub fn shared() -> &'static HttpClient {
+        SHARED.get_or_init(|| {
+            HttpClient::new(&HttpConfig::default())
+                .expect("Default HTTP client settings are valid!")
+        })
+    }
+
+    /// Makes this client the shared one. Must be called before the first
+    /// request, returns false if the shared client is already in use.
+    pub fn make_shared(self) -> bool {
+        SHARED.set(self).is_ok()
+    }
+
+    /// Sends a GET request with the extra headers. Connection failures,
+    /// timeouts and server errors are retried. Other error statuses are
+    /// returned as responses, so that the caller can handle them.
+    pub fn get(&self, url: &str, headers: HeaderMap) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            let result = self.client.get(url).headers(headers.clone()).send();
+            let retry = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(err) => err.is_timeout() || err.is_connect(),
+            };
+            if !retry || attempt >= self.retries {
+                return result;
+            }
+            thread::sleep(self.retry_delay * 2u32.saturating_pow(attempt));
+            attempt += 1;
+        }
+    }
+}
+
+/// Fetches the page as text. HTTP error statuses are network errors.
+impl HttpFetcher for HttpClient {
+    fn fetch(&self, url: &str) -> Result<String, DriverCheckError> {
+        self.get(url, HeaderMap::new())
+            .and_then(Response::error_for_status)
+            .map_err(DriverCheckError::Network)?
+            .text()
+            .map_err(DriverCheckError::InvalidUtf8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    /// Settings with short timeouts and delays for the tests.
+    fn test_config() -> HttpConfig {
+        HttpConfig::new()
+            .connect_timeout(Duration::from_secs(2))
+            .timeout(Duration::from_millis(300))
+            .retries(2, Duration::from_millis(10))
+    }
+
+    /// Test that the server errors are retried until the server recovers.
+    #[test]
+    fn fetch_retries_server_errors() {
+        let count = AtomicUsize::new(0);
+        let server = TestServer::start(move |_| match count.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Response::status(503),
+            _ => Response::ok("recovered"),
+        });
+        let client = HttpClient::new(&test_config()).unwrap();
+        assert_eq!(client.fetch(&server.url).unwrap(), "recovered");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    /// Test that the retries end and the error is reported.
+    #[test]
+    fn fetch_gives_up() {
+        let server = TestServer::start(|_| Response::status(500));
+        let client = HttpClient::new(&test_config()).unwrap();
+        assert!(matches!(
+            client.fetch(&server.url),
+            Err(DriverCheckError::Network(_))
+        ));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    /// Test that the client errors are not retried.
+    #[test]
+    fn fetch_not_found() {
+        let server = TestServer::start(|_| Response::status(404));
+        let client = HttpClient::new(&test_config()).unwrap();
+        assert!(client.fetch(&server.url).is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    /// Test that a stalling server times out and the request is retried.
+    #[test]
+    fn fetch_times_out() {
+        let count = AtomicUsize::new(0);
+        let server = TestServer::start(move |_| {
+            if count.fetch_add(1, Ordering::SeqCst) == 0 {
+                thread::sleep(Duration::from_secs(2));
+            }
+            Response::ok("fast")
+        });
+        let client = HttpClient::new(&test_config()).unwrap();
+        let start = Instant::now();
+        assert_eq!(client.fetch(&server.url).unwrap(), "fast");
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let client = HttpClient::new(&test_config().retries(0, Duration::ZERO)).unwrap();
+        let stalling = TestServer::start(|_| {
+            thread::sleep(Duration::from_secs(2));
+            Response::ok("slow")
+        });
+        match client.fetch(&stalling.url) {
+            Err(DriverCheckError::Network(err)) => assert!(err.is_timeout()),
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    /// Test that the user agent is sent.
+    #[test]
+    fn fetch_user_agent() {
+        let server = TestServer::serve(&[("/", "ok")]);
+        HttpClient::new(&test_config())
+            .unwrap()
+            .fetch(&server.url)
+            .unwrap();
+        let client = HttpClient::new(&test_config().user_agent("checker/1.0")).unwrap();
+        client.fetch(&server.url).unwrap();
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].header("User-Agent"),
+            Some(format!("geforcedrvchk3/{VERSION}").as_str())
+        );
+        assert_eq!(requests[1].header("User-Agent"), Some("checker/1.0"));
+        assert!(matches!(
+            HttpClient::new(&test_config().user_agent("bad\nagent")),
+            Err(DriverCheckError::UserAgent(_))
+        ));
+    }
+
+    /// Test that the requests go through the proxy, except for the hosts
+    /// excluded from it.
+    #[test]
+    fn fetch_through_proxy() {
+        let proxy = TestServer::start(|_| Response::ok("proxied"));
+        let client = HttpClient::new(&test_config().http_proxy(&proxy.url)).unwrap();
+        let page = client
+            .fetch("http://drivers.example.com/latest.txt")
+            .unwrap();
+        assert_eq!(page, "proxied");
+        assert_eq!(
+            proxy.requests()[0].path,
+            "http://drivers.example.com/latest.txt"
+        );
+
+        let server = TestServer::serve(&[("/", "direct")]);
+        let client = HttpClient::new(
+            &test_config()
+                .proxy(&proxy.url)
+                .no_proxy(["127.0.0.1", "localhost"]),
+        )
+        .unwrap();
+        assert_eq!(client.fetch(&server.url).unwrap(), "direct");
+        assert_eq!(proxy.requests().len(), 1);
+    }
+
+    /// Test that an unreadable or invalid CA bundle is reported.
+    #[test]
+    fn new_invalid_ca_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = HttpClient::new(&test_config().ca_bundle(dir.path().join("missing.pem")));
+        assert!(matches!(result, Err(DriverCheckError::CaBundle { .. })));
+
+        let path = dir.path().join("invalid.pem");
+        fs::write(
+            &path,
+            "-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let result = HttpClient::new(&test_config().ca_bundle(&path));
+        assert!(matches!(result, Err(DriverCheckError::HttpClient(_))));
+    }
+}
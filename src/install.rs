@@ -0,0 +1,406 @@
+//! Unattended installation of the downloaded driver installation packages.
+//!
+//! The installer is run through a `CommandRunner`, so that the installation
+//! can be tested with stub executables instead of the real installer.
+
+use crate::{get_installed_version, DriverCheckError, DriverVersion};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Switches for an unattended installation with the Windows installer.
+pub const WINDOWS_INSTALL_ARGS: &[&str] = &["-s", "-noreboot", "-clean"];
+
+/// Switches for an unattended installation with the Linux .run installer.
+pub const LINUX_INSTALL_ARGS: &[&str] = &["--silent"];
+
+/// Exit codes of a successful installation needing a reboot, i.e.
+/// ERROR_SUCCESS_REBOOT_INITIATED and ERROR_SUCCESS_REBOOT_REQUIRED.
+pub const REBOOT_EXIT_CODES: &[i32] = &[1641, 3010];
+
+/// Result of a confirmed installation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallOutcome {
+    /// The installed driver version is in use.
+    Installed(DriverVersion),
+    /// The driver is installed, but the old one stays in use until a
+    /// reboot.
+    RebootRequired,
+}
+
+/// Result of a finished command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// Exit code of the command, `None` if the command was terminated by a
+    /// signal.
+    pub status: Option<i32>,
+    /// Standard output of the command followed by its standard error.
+    pub output: String,
+}
+
+/// Runs commands for the installation. Implemented for `SystemRunner` and for
+/// closures, so that tests can replace the real installer.
+pub trait CommandRunner {
+    /// Runs the program with the arguments and waits for it to finish.
+    fn run(&self, program: &Path, args: &[String]) -> io::Result<CommandOutput>;
+}
+
+impl<F> CommandRunner for F
+where
+    F: Fn(&Path, &[String]) -> io::Result<CommandOutput>,
+{
+    fn run(&self, program: &Path, args: &[String]) -> io::Result<CommandOutput> {
+        self(program, args)
+    }
+}
+
+/// Runs the commands as child processes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &Path, args: &[String]) -> io::Result<CommandOutput> {
+        let output = Command::new(program).args(args).output()?;
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok(CommandOutput {
+            status: output.status.code(),
+            output: text,
+        })
+    }
+}
+
+/// Runs a driver installer unattended.
+#[derive(Debug, Clone)]
+pub struct SilentInstall<R = SystemRunner> {
+    runner: R,
+    args: Option<Vec<String>>,
+    log_file: Option<PathBuf>,
+}
+
+impl SilentInstall {
+    /// Creates an installation running the installer as a child process with
+    /// the default switches of the installer.
+    pub fn new() -> SilentInstall {
+        SilentInstall {
+            runner: SystemRunner,
+            args: None,
+            log_file: None,
+        }
+    }
+}
+
+impl Default for SilentInstall {
+    fn default() -> Self {
+        SilentInstall::new()
+    }
+}
+
+impl<R: CommandRunner> SilentInstall<R> {
+    /// Sets the runner used for running the installer.
+    pub fn runner<T: CommandRunner>(self, runner: T) -> SilentInstall<T> {
+        SilentInstall {
+            runner,
+            args: self.args,
+            log_file: self.log_file,
+        }
+    }
+
+    /// Sets the installer switches, replacing the default ones.
+    pub fn args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args = Some(args.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the file the output of the installer is written to.
+    pub fn log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Returns the switches the installer is run with. By default the
+    /// switches are selected by the installer type, i.e. `LINUX_INSTALL_ARGS`
+    /// for ".run" files and `WINDOWS_INSTALL_ARGS` for the others.
+    pub fn installer_args(&self, installer: &Path) -> Vec<String> {
+        match &self.args {
+            Some(args) => args.clone(),
+            None if is_run_installer(installer) => LINUX_INSTALL_ARGS
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+            None => WINDOWS_INSTALL_ARGS
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+        }
+    }
+
+    /// Runs the installer and returns its output. The output is also
+    /// written to the log file, if one is set, before the exit code is
+    /// checked. The exit codes telling that a reboot is needed are
+    /// successful.
+    pub fn install(&self, installer: &Path) -> Result<CommandOutput, DriverCheckError> {
+        let launch_error = |source| DriverCheckError::InstallerLaunch {
+            path: installer.to_path_buf(),
+            source,
+        };
+        make_executable(installer).map_err(launch_error)?;
+        let output = self
+            .runner
+            .run(installer, &self.installer_args(installer))
+            .map_err(launch_error)?;
+        if let Some(log_file) = &self.log_file {
+            fs::write(log_file, &output.output).map_err(|source| DriverCheckError::InstallLog {
+                path: log_file.clone(),
+                source,
+            })?;
+        }
+        match output.status {
+            Some(0) => Ok(output),
+            Some(code) if REBOOT_EXIT_CODES.contains(&code) => Ok(output),
+            status => Err(DriverCheckError::InstallerExit(status.unwrap_or(-1))),
+        }
+    }
+
+    /// Tells whether the old driver may stay in use until a reboot after
+    /// the installation: the installer asked for a reboot, the reboot was
+    /// suppressed with "-noreboot", or the installer is a .run installer,
+    /// whose old kernel module stays loaded.
+    pub fn reboot_pending(&self, installer: &Path, output: &CommandOutput) -> bool {
+        output
+            .status
+            .is_some_and(|code| REBOOT_EXIT_CODES.contains(&code))
+            || is_run_installer(installer)
+            || self
+                .installer_args(installer)
+                .iter()
+                .any(|arg| arg.eq_ignore_ascii_case("-noreboot"))
+    }
+}
+
+/// Tells whether the installer is a Linux .run installer.
+fn is_run_installer(installer: &Path) -> bool {
+    installer.extension().is_some_and(|ext| ext == "run")
+}
+
+/// The downloaded .run installers are not executable by default.
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(path: &Path) -> io::Result<()> {
+    fs::metadata(path).map(|_| ())
+}
+
+/// Checks that the expected driver version is in use after the
+/// installation. The version is queried the same way as with
+/// `get_installed_version()`. If a reboot is pending, another version or a
+/// failing query, e.g. because of a driver and library mismatch, means that
+/// the new driver is used after the reboot.
+pub fn confirm_installed_version(
+    executable_name: &str,
+    expected: &DriverVersion,
+    reboot_pending: bool,
+) -> Result<InstallOutcome, DriverCheckError> {
+    match get_installed_version(executable_name) {
+        Ok(installed) if installed == *expected => Ok(InstallOutcome::Installed(installed)),
+        _ if reboot_pending => Ok(InstallOutcome::RebootRequired),
+        Ok(installed) => Err(DriverCheckError::InstallNotConfirmed {
+            expected: *expected,
+            installed,
+        }),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Creates an empty installer in a temporary directory.
+    fn installer(name: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, "").unwrap();
+        (dir, path)
+    }
+
+    /// Test that the default switches depend on the installer type.
+    #[test]
+    fn installer_args_default() {
+        let install = SilentInstall::new();
+        assert_eq!(
+            install.installer_args(Path::new("566.14-win11.exe")),
+            WINDOWS_INSTALL_ARGS
+        );
+        assert_eq!(
+            install.installer_args(Path::new("NVIDIA-Linux-x86_64-550.54.14.run")),
+            LINUX_INSTALL_ARGS
+        );
+        let install = install.args(["-s", "-n"]);
+        assert_eq!(
+            install.installer_args(Path::new("566.14-win11.exe")),
+            ["-s", "-n"]
+        );
+    }
+
+    /// Test that the installer is run with the switches and its output is
+    /// logged.
+    #[test]
+    fn install_success() {
+        let (dir, path) = installer("566.14-win11.exe");
+        let calls = RefCell::new(Vec::new());
+        let runner = |program: &Path, args: &[String]| {
+            calls
+                .borrow_mut()
+                .push((program.to_path_buf(), args.to_vec()));
+            Ok(CommandOutput {
+                status: Some(0),
+                output: "Installation complete\n".to_string(),
+            })
+        };
+        let log = dir.path().join("install.log");
+        let output = SilentInstall::new()
+            .runner(runner)
+            .log_file(&log)
+            .install(&path)
+            .unwrap();
+        assert_eq!(output.output, "Installation complete\n");
+        assert_eq!(
+            calls.into_inner(),
+            [(path, vec!["-s".into(), "-noreboot".into(), "-clean".into()])]
+        );
+        assert_eq!(fs::read_to_string(log).unwrap(), "Installation complete\n");
+    }
+
+    /// Test that a failing installer is reported with its exit code and the
+    /// output is logged anyway.
+    #[test]
+    fn install_failure() {
+        let (dir, path) = installer("566.14-win11.exe");
+        let runner = |_: &Path, _: &[String]| {
+            Ok(CommandOutput {
+                status: Some(3),
+                output: "Reboot pending\n".to_string(),
+            })
+        };
+        let log = dir.path().join("install.log");
+        let result = SilentInstall::new()
+            .runner(runner)
+            .log_file(&log)
+            .install(&path);
+        assert!(matches!(result, Err(DriverCheckError::InstallerExit(3))));
+        assert_eq!(fs::read_to_string(log).unwrap(), "Reboot pending\n");
+    }
+
+    /// Test that an installer asking for a reboot succeeds with a pending
+    /// reboot.
+    #[test]
+    fn install_reboot_required() {
+        let (_dir, path) = installer("566.14-win11.exe");
+        let runner = |_: &Path, _: &[String]| {
+            Ok(CommandOutput {
+                status: Some(3010),
+                output: String::new(),
+            })
+        };
+        let install = SilentInstall::new().runner(runner).args(["-s"]);
+        let output = install.install(&path).unwrap();
+        assert!(install.reboot_pending(&path, &output));
+
+        let output = CommandOutput {
+            status: Some(0),
+            output: String::new(),
+        };
+        assert!(!install.reboot_pending(&path, &output));
+        assert!(SilentInstall::new().reboot_pending(&path, &output));
+        let (_dir, path) = installer("NVIDIA-Linux-x86_64-550.54.14.run");
+        assert!(install.reboot_pending(&path, &output));
+    }
+
+    /// Test that a missing installer is reported.
+    #[test]
+    fn install_missing_installer() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = SilentInstall::new().install(&dir.path().join("missing.exe"));
+        assert!(matches!(
+            result,
+            Err(DriverCheckError::InstallerLaunch { .. })
+        ));
+    }
+
+    /// Test that a stub installer is run as a child process.
+    #[cfg(unix)]
+    #[test]
+    fn install_stub_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("NVIDIA-Linux-x86_64-550.54.14.run");
+        fs::write(
+            &path,
+            "#!/bin/sh\necho \"installing $@\"\necho warning >&2\n",
+        )
+        .unwrap();
+        let output = SilentInstall::new().install(&path).unwrap();
+        assert_eq!(output.status, Some(0));
+        assert_eq!(output.output, "installing --silent\nwarning\n");
+
+        fs::write(&path, "#!/bin/sh\nexit 5\n").unwrap();
+        let result = SilentInstall::new().install(&path);
+        assert!(matches!(result, Err(DriverCheckError::InstallerExit(5))));
+    }
+
+    /// Test that the installed version is confirmed with a stub nvidia-smi.
+    #[cfg(unix)]
+    #[test]
+    fn confirm_installed_version_stub() {
+        let (_dir, smi) = installer("nvidia-smi");
+        fs::write(&smi, "#!/bin/sh\necho 'Driver Version: 566.14'\n").unwrap();
+        make_executable(&smi).unwrap();
+        let smi = smi.to_str().unwrap();
+        assert_eq!(
+            confirm_installed_version(smi, &DriverVersion::new(566, 14), false).unwrap(),
+            InstallOutcome::Installed(DriverVersion::new(566, 14))
+        );
+        let result = confirm_installed_version(smi, &DriverVersion::new(566, 36), false);
+        assert!(matches!(
+            result,
+            Err(DriverCheckError::InstallNotConfirmed { .. })
+        ));
+        assert_eq!(
+            confirm_installed_version(smi, &DriverVersion::new(566, 36), true).unwrap(),
+            InstallOutcome::RebootRequired
+        );
+    }
+
+    /// Test that a failing nvidia-smi is a pending reboot only if one is
+    /// expected.
+    #[cfg(unix)]
+    #[test]
+    fn confirm_installed_version_mismatch() {
+        let (_dir, smi) = installer("nvidia-smi");
+        fs::write(
+            &smi,
+            "#!/bin/sh
+echo 'Failed to initialize NVML: Driver/library version mismatch'
+exit 18
+",
+        )
+        .unwrap();
+        make_executable(&smi).unwrap();
+        let smi = smi.to_str().unwrap();
+        let expected = DriverVersion::new(550, 54);
+        assert_eq!(
+            confirm_installed_version(smi, &expected, true).unwrap(),
+            InstallOutcome::RebootRequired
+        );
+        assert!(confirm_installed_version(smi, &expected, false).is_err());
+    }
+}
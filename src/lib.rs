@@ -1,120 +1,319 @@
 //! This library provides tools for querying NVIDIA GeForce graphics driver
-//! version information from the installed driver and from the driver
-//! download page.
+//! version information from the installed driver and from the available
+//! driver releases.
 //!
-//! The library fetches information for GTX 1070 Ti card for 64-bit Windows
-//! operating system. The driver should be the same for other modern NVIDIA
-//! cards.
+//! The installed driver and GPUs are detected with nvidia-smi, or from the
+//! kernel module under Linux. The available releases are looked up from a
+//! `DriverSource`:
 //!
-//! The page this library is using for fetching information is this:
-//! <https://www.geforce.com/drivers>
+//! - `NvidiaApiSource` queries the driver lookup service of NVIDIA with a
+//!   `DriverQuery`, i.e. the product, operating system, language and
+//!   channel. The products come from the `Catalog`.
+//! - `LinuxFeedSource` reads the Linux driver feed.
+//! - `MirrorSource` reads the manifest of a local driver mirror, which is
+//!   populated with `MirrorSync`.
+//!
+//! The library can also download, verify and install a driver release, and
+//! render its release notes as plain text.
+
+mod cache;
+mod catalog;
+mod config;
+mod download;
+mod error;
+mod http;
+mod install;
+mod linux;
+mod mirror;
+mod notes;
+mod query;
+mod release;
+mod report;
+mod smi;
+mod source;
+#[cfg(test)]
+mod test_server;
+mod verify;
 
-use json;
-use regex::Regex;
-use reqwest::blocking;
-use std::{env, path::Path, path::PathBuf, process::Command};
+pub use cache::{default_cache_dir, format_age, ResponseCache, DEFAULT_CACHE_TTL};
+pub use catalog::{parse_lookup_values, Catalog, LookupType, LookupValue, Product};
+pub use config::{
+    default_config_path, CacheConfig, Config, DefaultAction, LookupId, NetworkConfig,
+    NotificationConfig, ProxyConfig, QueryConfig, CONFIG_FILE,
+};
+pub use download::{download_file, download_file_name, DownloadProgress};
+pub use error::DriverCheckError;
+pub use http::{HttpClient, HttpConfig};
+pub use install::{
+    confirm_installed_version, CommandOutput, CommandRunner, InstallOutcome, SilentInstall,
+    SystemRunner, LINUX_INSTALL_ARGS, REBOOT_EXIT_CODES, WINDOWS_INSTALL_ARGS,
+};
+pub use linux::{
+    get_linux_version_information, list_linux_drivers, parse_branch_listing, parse_latest_txt,
+    LinuxFeed,
+};
+pub use mirror::{
+    MirrorManifest, MirrorRelease, MirrorSource, MirrorSync, MirrorUpdate, Retention, MANIFEST_FILE,
+};
+pub use notes::{html_to_text, ReleaseNotes};
+pub use query::{DriverChannel, DriverQuery};
+pub use release::{
+    detect_channel, format_file_size, latest_stable_and_beta, list_available_drivers,
+    parse_driver_releases, parse_file_size, DriverRelease, ReleaseDate,
+};
+pub use report::UpdateReport;
+pub use smi::{
+    find_driver_mismatches, find_executable, get_installed_gpus, get_installed_version,
+    get_kernel_module_version, get_smi_log, parse_gpu_query, parse_kernel_module_version,
+    parse_smi_log, DriverMismatch, GpuInfo, SmiLog, SmiLogGpu, KERNEL_MODULE_VERSION_FILES,
+};
+pub use source::{DriverSource, HttpFetcher, LinuxFeedSource, MemoFetcher, NvidiaApiSource};
+pub use verify::{parse_sha256, sha256_file, sha256_sidecar_path, verify_installer, ExpectedSize};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+#[cfg(windows)]
+use std::env;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::process::{Command, ExitStatus};
+use std::str::FromStr;
 
 use std::io::Write; // Just for flush()
-use std::io::{stdin, stdout};
+use std::io::{self, stdin, stdout};
 
 pub const VERSION: &str = "0.5.1";
+#[cfg(windows)]
 pub const SMI: &str = r"nvidia-smi.exe";
-const NVIDIA_URL: &str = r"https://gfwsl.geforce.com/services_toolkit/services/com/nvidia/services/AjaxDriverService.php?func=DriverManualLookup&psid=101&pfid=859&osID=57&languageCode=1033&beta=0&isWHQL=0&dltype=-1&dch=1&upCRD=0&qnf=0&sort1=0&numberOfResults=10";
+#[cfg(not(windows))]
+pub const SMI: &str = r"nvidia-smi";
+
+/// NVIDIA display driver version, e.g. "552.12".
+///
+/// The minor part is compared as an integer, so "560.9" is older than
+/// "560.10". The number of digits in the minor part is remembered only for
+/// displaying the version the same way it was written.
+///
+/// Linux drivers have a third part, e.g. "550.54.14". A missing third part
+/// is compared as zero.
+///
+/// Besides these forms, the Windows-style "31.0.15.5212" form reported
+/// by the device manager is also accepted. The last five digits of such a
+/// version are the NVIDIA version, so "31.0.15.5212" is "552.12".
+#[derive(Debug, Clone, Copy)]
+pub struct DriverVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: Option<u32>,
+    minor_width: usize,
+    patch_width: usize,
+}
+
+impl DriverVersion {
+    /// Creates a new version. The minor part is displayed with two digits.
+    pub fn new(major: u32, minor: u32) -> DriverVersion {
+        DriverVersion {
+            major,
+            minor,
+            patch: None,
+            minor_width: 2,
+            patch_width: 0,
+        }
+    }
+
+    /// Converts Windows-style "AA.BB.CC.DDDD" version to NVIDIA version.
+    ///
+    /// Only versions looking like a driver store version are accepted, i.e.
+    /// AA is at least 20, CC has two digits and DDDD at most four, so that
+    /// e.g. "1.2.3.4" is not taken for a driver version.
+    fn from_windows_version(parts: &[&str]) -> Option<DriverVersion> {
+        let model: u32 = parts[0].parse().ok()?;
+        let branch: u32 = parts[2].parse().ok()?;
+        let build = parts[3];
+        if model < 20 || !(10..=99).contains(&branch) || build.len() > 4 {
+            return None;
+        }
+        let digits = format!("{}{:0>4}", parts[2], build);
+        let digits = &digits[digits.len().saturating_sub(5)..];
+        if digits.len() < 5 {
+            return None;
+        }
+        let major = digits[..3].parse().ok()?;
+        let minor = digits[3..].parse().ok()?;
+        Some(DriverVersion::new(major, minor))
+    }
+}
+
+impl FromStr for DriverVersion {
+    type Err = DriverCheckError;
+
+    fn from_str(s: &str) -> Result<DriverVersion, DriverCheckError> {
+        let invalid = || DriverCheckError::ParseVersion(s.to_string());
+        let parts: Vec<&str> = s.trim().split('.').collect();
+        if parts
+            .iter()
+            .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+        {
+            return Err(invalid());
+        }
+        match parts.len() {
+            2 => Ok(DriverVersion {
+                major: parts[0].parse().map_err(|_| invalid())?,
+                minor: parts[1].parse().map_err(|_| invalid())?,
+                patch: None,
+                minor_width: parts[1].len(),
+                patch_width: 0,
+            }),
+            3 => Ok(DriverVersion {
+                major: parts[0].parse().map_err(|_| invalid())?,
+                minor: parts[1].parse().map_err(|_| invalid())?,
+                patch: Some(parts[2].parse().map_err(|_| invalid())?),
+                minor_width: parts[1].len(),
+                patch_width: parts[2].len(),
+            }),
+            4 => DriverVersion::from_windows_version(&parts).ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Serialized in the displayed form, e.g. "552.12".
+impl Serialize for DriverVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserialized from the displayed form, e.g. "552.12".
+impl<'de> Deserialize<'de> for DriverVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let version = String::deserialize(deserializer)?;
+        version.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for DriverVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.major,
+            self.minor,
+            width = self.minor_width
+        )?;
+        match self.patch {
+            Some(patch) => write!(f, ".{:0width$}", patch, width = self.patch_width),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PartialEq for DriverVersion {
+    fn eq(&self, other: &DriverVersion) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DriverVersion {}
+
+impl PartialOrd for DriverVersion {
+    fn partial_cmp(&self, other: &DriverVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DriverVersion {
+    fn cmp(&self, other: &DriverVersion) -> Ordering {
+        (self.major, self.minor, self.patch.unwrap_or(0)).cmp(&(
+            other.major,
+            other.minor,
+            other.patch.unwrap_or(0),
+        ))
+    }
+}
+
+impl Hash for DriverVersion {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.major.hash(state);
+        self.minor.hash(state);
+        self.patch.unwrap_or(0).hash(state);
+    }
+}
 
 /// Fetches contents of the URL and returns them as a string. It is assumed
 /// that the contents are UTF-8 encoded.
 ///
-/// If there is an error, then an error message is returned as a result.
-pub fn get_page(url: &str) -> Result<String, &'static str> {
-    let response = blocking::get(url);
-    match response {
-        Ok(resp) => resp
-            .text()
-            .or(Err("The page has invalid UTF-8 characters!")),
-        Err(_) => Err("Unable to access the online resources!"),
-    }
+/// The page is fetched with the shared `HttpClient`, so the request has
+/// timeouts and is retried on server errors. If there is an error, including
+/// an HTTP error status, then the error is returned as a result.
+pub fn get_page(url: &str) -> Result<String, DriverCheckError> {
+    HttpClient::shared().fetch(url)
 }
 
 /// Retrieves the latest available driver installation package version number
-/// and a download URL as a tuple. The version number should be formatted as
-/// "XXX.YY", so it should be possible to convert it to a double.
+/// and a download URL as a tuple.
 ///
-/// Takes as an argument a function that is able to retrieve data from the server and
-/// return is as a string (JSON). Just use get_page() here.
+/// Takes as an argument a fetcher that is able to retrieve data from the server and
+/// return is as a string (JSON). Just use get_page() here. The query selects
+/// the product, operating system etc. the driver is looked up for.
 ///
-/// If the information cannot be retrieved, then an error message is provided
-/// as a result.
+/// If the information cannot be retrieved, then an error is provided as a
+/// result.
 pub fn get_available_version_information(
-    get_page: fn(&str) -> Result<String, &'static str>,
-) -> Result<(String, String), &'static str> {
-    let page = get_page(NVIDIA_URL)?;
-    let data = json::parse(&page).or(Err("Incorrect information at the online resource!"))?;
-    let json_version = &data["IDS"][0]["downloadInfo"]["Version"];
-    let json_url = &data["IDS"][0]["downloadInfo"]["DownloadURL"];
-    let version = json_version
-        .as_str()
-        .ok_or("Cannot find version information from the online resource!")?;
-    let url = json_url
-        .as_str()
-        .ok_or("Cannot find download URL information from the online resource!")?;
-    Ok((version.to_string(), url.to_string()))
-}
-
-/// Retrieves installed display driver version as a string. The version number
-/// should be formatted as "XXX.YY", so it should be possible to convert it to
-/// a double.
-///
-/// If the version number is not available (e.g. nvidia-smi.exe could not be
-/// found), then an error message is provided as a result.
-pub fn get_installed_version(executable_name: &str) -> Result<String, &'static str> {
-    let nvidiasmi = get_nvidia_smi_location(&executable_name)?;
-    let output = Command::new(nvidiasmi).output().or(Err(
-        "Couldn't detect installed version. Maybe the driver is not installed?",
-    ))?;
-    let pattern = Regex::new(r"Driver Version: ([0-9]+\.[0-9]+)").unwrap();
-    let nvsmi = String::from_utf8_lossy(&output.stdout);
-    let captures = pattern
-        .captures(&nvsmi)
-        .ok_or("Cannot find installed version information!")?;
-    Ok(String::from(&captures[1]))
-}
-
-/// Find nvidia-smi.exe and return full path.
-fn get_nvidia_smi_location(executable_name: &str) -> Result<String, &'static str> {
-    let nvidia_smi_path_old: PathBuf = ["NVIDIA Corporation", "NVSMI", &executable_name]
-        .iter()
-        .collect();
-    let nvidia_smi_path_new: PathBuf = ["System32", &executable_name].iter().collect();
-    let mut nvidiasmi = PathBuf::new();
-    nvidiasmi.push(env::var("windir").expect("Environment variable 'windir' not found!"));
-    nvidiasmi.extend(&nvidia_smi_path_new);
-    if Path::new(&nvidiasmi).exists() == false {
-        let mut nvidiasmi = PathBuf::new();
-        nvidiasmi.push(
-            env::var("ProgramFiles").expect("Environment variable 'ProgramFiles' not found!"),
-        );
-        nvidiasmi.extend(&nvidia_smi_path_old);
-        if nvidiasmi.exists() == false {
-            Err("Couldn't detect location for nvidia-smi. Maybe the driver is not installed?")
-        } else {
-            Ok(String::from(nvidiasmi.to_string_lossy()))
-        }
-    } else {
-        Ok(String::from(nvidiasmi.to_string_lossy()))
-    }
+    fetcher: &impl HttpFetcher,
+    query: &DriverQuery,
+) -> Result<(DriverVersion, String), DriverCheckError> {
+    let release = list_available_drivers(fetcher, query)?
+        .into_iter()
+        .next()
+        .ok_or(DriverCheckError::MissingField("version"))?;
+    Ok((release.version, release.download_url))
 }
 
 /// Starts the default web browser if a valid URL is given. Note that the
 /// operation is executed simply by calling "start" command at the
 /// command-line and the URL is not sanitized in any way. It's possible to run
 /// arbitrary commands with this function.
-pub fn start_browser(url: &str) {
-    Command::new(env::var("ComSpec").expect("Environment variable 'ComSpec' not found!"))
+///
+/// Under other operating systems than Windows, "xdg-open" is used instead.
+#[cfg(windows)]
+pub fn start_browser(url: &str) -> Result<(), DriverCheckError> {
+    let shell = env::var("ComSpec").map_err(|_| {
+        DriverCheckError::BrowserLaunch(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Environment variable 'ComSpec' not found!",
+        ))
+    })?;
+    let status = Command::new(shell)
         .arg("/c")
         .arg("start")
         .arg(url)
-        .spawn()
-        .unwrap();
+        .status()
+        .map_err(DriverCheckError::BrowserLaunch)?;
+    browser_status(status)
+}
+
+/// Starts the default web browser if a valid URL is given by calling
+/// "xdg-open".
+#[cfg(not(windows))]
+pub fn start_browser(url: &str) -> Result<(), DriverCheckError> {
+    let status = Command::new("xdg-open")
+        .arg(url)
+        .status()
+        .map_err(DriverCheckError::BrowserLaunch)?;
+    browser_status(status)
+}
+
+/// Fails if the command starting the browser exited unsuccessfully, e.g.
+/// because no browser is available on a headless system.
+fn browser_status(status: ExitStatus) -> Result<(), DriverCheckError> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(DriverCheckError::BrowserLaunch(io::Error::other(format!(
+            "the browser command failed with {status}"
+        ))))
+    }
 }
 
 /// Asks message from user and lists options. The default option is zero-based
@@ -150,7 +349,7 @@ pub fn ask_confirmation(message: &str, options: &[char], default: usize) -> usiz
         stdout().flush().unwrap();
         stdin().read_line(&mut input).unwrap();
 
-        if input.trim().len() == 0 {
+        if input.trim().is_empty() {
             break default;
         } else {
             let pos = &options.iter().position(|&x| {
@@ -171,19 +370,30 @@ mod tests {
     /// Test that get_page() is able to fetch a web page via http connection.
     #[test]
     fn get_page_success() {
-        assert_eq!(get_page("http://example.com/").is_ok(), true);
+        assert!(get_page("http://example.com/").is_ok());
+    }
+
+    /// Test that a failing browser command is reported as an error.
+    #[cfg(not(windows))]
+    #[test]
+    fn browser_status_failure() {
+        assert!(browser_status(Command::new("true").status().unwrap()).is_ok());
+        assert!(matches!(
+            browser_status(Command::new("false").status().unwrap()),
+            Err(DriverCheckError::BrowserLaunch(_))
+        ));
     }
 
     /// Test that get_page() is able to fetch a web page via https connection.
     #[test]
     fn get_page_ssl_success() {
-        assert_eq!(get_page("https://example.com/").is_ok(), true);
+        assert!(get_page("https://example.com/").is_ok());
     }
 
     /// Test that get_page() handles non-existent URL correctly.
     #[test]
     fn get_page_fail() {
-        assert_eq!(get_page("http://nonexistingdomain.local/").is_err(), true);
+        assert!(get_page("http://nonexistingdomain.local/").is_err());
     }
 
     /// Test that fetching installed driver version works.
@@ -192,23 +402,26 @@ mod tests {
     fn get_installed_version_success() {
         std::env::set_var("windir", ".");
         std::env::set_var("ProgramFiles", ".");
-        assert_eq!(get_installed_version("smi-stub.bat").unwrap(), "123.45");
+        assert_eq!(
+            get_installed_version("smi-stub.bat").unwrap().to_string(),
+            "123.45"
+        );
     }
 
     /// Test that fetching available driver data works.
     #[test]
     fn get_available_version_information_success() {
-        assert_eq!(
-            get_available_version_information(get_test_page).is_ok(),
-            true
-        );
+        assert!(get_available_version_information(&get_test_page, &DriverQuery::new()).is_ok());
     }
 
     /// Test that fetching available driver version works.
     #[test]
     fn get_available_version_number_success() {
         assert_eq!(
-            get_available_version_information(get_test_page).unwrap().0,
+            get_available_version_information(&get_test_page, &DriverQuery::new())
+                .unwrap()
+                .0
+                .to_string(),
             "123.45"
         );
     }
@@ -217,14 +430,242 @@ mod tests {
     #[test]
     fn get_available_version_url_success() {
         assert_eq!(
-            get_available_version_information(get_test_page).unwrap().1,
+            get_available_version_information(&get_test_page, &DriverQuery::new())
+                .unwrap()
+                .1,
             "https://example.com/test.exe"
         );
     }
 
+    /// Test that a plain "XXX.YY" version is parsed.
+    #[test]
+    fn driver_version_parse_success() {
+        let version: DriverVersion = "552.12".parse().unwrap();
+        assert_eq!(version, DriverVersion::new(552, 12));
+    }
+
+    /// Test that a Windows-style version is converted to NVIDIA version.
+    #[test]
+    fn driver_version_parse_windows_success() {
+        let version: DriverVersion = "31.0.15.5212".parse().unwrap();
+        assert_eq!(version, DriverVersion::new(552, 12));
+        let version: DriverVersion = "32.0.15.6094".parse().unwrap();
+        assert_eq!(version.to_string(), "560.94");
+        let version: DriverVersion = "31.0.15.3007".parse().unwrap();
+        assert_eq!(version.to_string(), "530.07");
+        let version: DriverVersion = "23.21.13.8813".parse().unwrap();
+        assert_eq!(version.to_string(), "388.13");
+    }
+
+    /// Test that a four-part version not looking like a Windows driver
+    /// version is rejected.
+    #[test]
+    fn driver_version_parse_windows_fail() {
+        for version in [
+            "1.2.3.4",
+            "9.18.13.4192",
+            "31.0.5.5212",
+            "31.0.150.5212",
+            "31.0.15.52120",
+        ] {
+            assert!(matches!(
+                version.parse::<DriverVersion>(),
+                Err(DriverCheckError::ParseVersion(_))
+            ));
+        }
+    }
+
+    /// Test that invalid versions are rejected.
+    #[test]
+    fn driver_version_parse_fail() {
+        assert!("".parse::<DriverVersion>().is_err());
+        assert!("552".parse::<DriverVersion>().is_err());
+        assert!("552.x".parse::<DriverVersion>().is_err());
+        assert!("552.12.1.2.3".parse::<DriverVersion>().is_err());
+        assert!("-1.12".parse::<DriverVersion>().is_err());
+    }
+
+    /// Test that a Linux-style version with three parts is parsed.
+    #[test]
+    fn driver_version_parse_linux_success() {
+        let version: DriverVersion = "535.104.05".parse().unwrap();
+        assert_eq!(version.patch, Some(5));
+        assert_eq!(version.to_string(), "535.104.05");
+        assert!(version > "535.98".parse().unwrap());
+        assert!(version < "550.54.14".parse().unwrap());
+        assert_eq!(
+            "550.54.0".parse::<DriverVersion>().unwrap(),
+            DriverVersion::new(550, 54)
+        );
+    }
+
+    /// Test that the minor part is compared as an integer.
+    #[test]
+    fn driver_version_ordering() {
+        let older: DriverVersion = "560.9".parse().unwrap();
+        let newer: DriverVersion = "560.10".parse().unwrap();
+        assert!(older < newer);
+        assert!("552.12".parse::<DriverVersion>().unwrap() < newer);
+        assert_eq!("560.09".parse::<DriverVersion>().unwrap(), older);
+    }
+
+    /// Test that trailing zeros are preserved when displaying a version.
+    #[test]
+    fn driver_version_display() {
+        assert_eq!(
+            "552.10".parse::<DriverVersion>().unwrap().to_string(),
+            "552.10"
+        );
+        assert_eq!(
+            "560.9".parse::<DriverVersion>().unwrap().to_string(),
+            "560.9"
+        );
+        assert_eq!(DriverVersion::new(560, 9).to_string(), "560.09");
+    }
+
+    /// Test that invalid JSON is reported as such.
+    #[test]
+    fn get_available_version_information_malformed_json() {
+        assert!(matches!(
+            get_available_version_information(
+                &|_: &str| Ok("{ \"IDS\" : [".to_string()),
+                &DriverQuery::new()
+            ),
+            Err(DriverCheckError::MalformedJson(_))
+        ));
+    }
+
+    /// Test that a missing version field is reported as such.
+    #[test]
+    fn get_available_version_information_missing_field() {
+        assert!(matches!(
+            get_available_version_information(
+                &|_: &str| Ok(r#"{ "IDS" : [] }"#.to_string()),
+                &DriverQuery::new()
+            ),
+            Err(DriverCheckError::MissingField("version"))
+        ));
+    }
+
+    /// Test that an invalid version number is reported as such.
+    #[test]
+    fn driver_version_parse_error() {
+        match "552.x".parse::<DriverVersion>() {
+            Err(DriverCheckError::ParseVersion(value)) => assert_eq!(value, "552.x"),
+            _ => panic!("Expected ParseVersion error"),
+        }
+    }
+
+    /// Test that the default query renders the GTX 1070 Ti lookup URL.
+    #[test]
+    fn driver_query_default_url() {
+        assert_eq!(
+            DriverQuery::new().url(),
+            r"https://gfwsl.geforce.com/services_toolkit/services/com/nvidia/services/AjaxDriverService.php?func=DriverManualLookup&psid=101&pfid=859&osID=57&languageCode=1033&beta=0&isWHQL=0&dltype=-1&dch=1&upCRD=0&qnf=0&sort1=0&numberOfResults=10"
+        );
+    }
+
+    /// Test that the channel selects the matching lookup parameters.
+    #[test]
+    fn driver_query_channel_url() {
+        let url = |channel| DriverQuery::new().channel(channel).url();
+        assert!(url(DriverChannel::GameReady).contains("&upCRD=0&qnf=0&"));
+        assert!(url(DriverChannel::Studio).contains("&upCRD=1&qnf=0&"));
+        assert!(url(DriverChannel::ProductionBranch).contains("&upCRD=0&qnf=0&"));
+        assert!(url(DriverChannel::NewFeatureBranch).contains("&upCRD=0&qnf=1&"));
+    }
+
+    /// Test that channel names are parsed and displayed.
+    #[test]
+    fn driver_channel_parse_and_display() {
+        assert_eq!(
+            "Studio".parse::<DriverChannel>().unwrap(),
+            DriverChannel::Studio
+        );
+        assert_eq!(
+            "game-ready".parse::<DriverChannel>().unwrap(),
+            DriverChannel::GameReady
+        );
+        assert_eq!(
+            "new_feature_branch".parse::<DriverChannel>().unwrap(),
+            DriverChannel::NewFeatureBranch
+        );
+        assert!(matches!(
+            "beta".parse::<DriverChannel>(),
+            Err(DriverCheckError::UnknownChannel(_))
+        ));
+        assert_eq!(
+            DriverChannel::ProductionBranch.to_string(),
+            "Production Branch"
+        );
+        assert_eq!(
+            DriverChannel::for_gpu_name("NVIDIA GeForce RTX 4070"),
+            DriverChannel::GEFORCE
+        );
+        assert_eq!(
+            DriverChannel::for_gpu_name("NVIDIA RTX A4000"),
+            DriverChannel::ENTERPRISE
+        );
+    }
+
+    /// Test that every query parameter ends up in the URL.
+    #[test]
+    fn driver_query_custom_url() {
+        let url = DriverQuery::new()
+            .product_series(127)
+            .product_family(1022)
+            .os(135)
+            .language(1031)
+            .dch(false)
+            .beta(true)
+            .whql(true)
+            .number_of_results(3)
+            .url();
+        assert!(url.contains("psid=127&pfid=1022&osID=135&languageCode=1031&beta=1&isWHQL=1"));
+        assert!(url.contains("&dch=0&"));
+        assert!(url.ends_with("&numberOfResults=3"));
+    }
+
+    /// Test that the query URL is passed to the page fetcher.
+    #[test]
+    fn get_available_version_information_uses_query() {
+        let query = DriverQuery::new().product_family(1022);
+        let result = get_available_version_information(
+            &|url: &str| {
+                assert!(url.contains("pfid=1022"));
+                get_test_page(url)
+            },
+            &query,
+        );
+        assert!(result.is_ok());
+    }
+
+    /// Test that fetching available driver metadata works.
+    #[test]
+    fn list_available_drivers_metadata_success() {
+        let releases = list_available_drivers(&get_test_page, &DriverQuery::new()).unwrap();
+        let release = &releases[0];
+        assert_eq!(release.name, "GeForce Game Ready Driver");
+        assert_eq!(release.release_date.unwrap().to_string(), "2024-11-19");
+        assert_eq!(
+            release.file_size.map(format_file_size).unwrap(),
+            "677.17 MB"
+        );
+        assert!(release.whql && release.recommended && !release.beta);
+        assert_eq!(
+            release.details_url.as_deref(),
+            Some("https://example.com/details/")
+        );
+        assert_eq!(release.other_notes.as_deref(), Some("<p>Notes</p>"));
+        assert_eq!(
+            release.brief_description.as_deref(),
+            Some("<p>Fixed bugs &amp; more</p>")
+        );
+    }
+
     /// Stub function for unit tests. Imitates get_page() function.
-    fn get_test_page(_url: &str) -> Result<String, &'static str> {
-        let json = r#"{ "Success" : "1", "IDS" : [ { "downloadInfo": { "Version" : "123.45", "DownloadURL" : "https://example.com/test.exe" } } ] }"#;
+    fn get_test_page(_url: &str) -> Result<String, DriverCheckError> {
+        let json = r#"{ "Success" : "1", "IDS" : [ { "downloadInfo": { "Version" : "123.45", "Name" : "GeForce%20Game%20Ready%20Driver", "ReleaseDateTime" : "Tue Nov 19, 2024", "DownloadURL" : "https://example.com/test.exe", "DownloadURLFileSize" : "677.17 MB", "IsBeta" : "0", "IsWHQL" : "1", "IsRecommended" : "1", "DetailsURL" : "https://example.com/details/", "OtherNotes" : "%3Cp%3ENotes%3C%2Fp%3E", "BriefDescription" : "%3Cp%3EFixed%20bugs%20%26amp%3B%20more%3C%2Fp%3E" } } ] }"#;
         Ok(json.to_string())
     }
 }
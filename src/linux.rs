@@ -0,0 +1,246 @@
+//! Available driver information for Linux from NVIDIA's Unix driver index at
+//! <https://download.nvidia.com/XFree86/Linux-x86_64/>.
+
+use crate::{DriverCheckError, DriverRelease, DriverVersion, HttpFetcher};
+use regex::Regex;
+
+const LINUX_FEED_URL: &str = r"https://download.nvidia.com/XFree86/Linux-x86_64/";
+
+/// Location of the Unix driver index and the driver branch to follow.
+///
+/// By default the latest driver of any branch is returned. When a branch is
+/// set, e.g. 535, the newest driver of that branch is returned instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinuxFeed {
+    pub base_url: String,
+    pub branch: Option<u32>,
+}
+
+impl Default for LinuxFeed {
+    fn default() -> LinuxFeed {
+        LinuxFeed {
+            base_url: LINUX_FEED_URL.to_string(),
+            branch: None,
+        }
+    }
+}
+
+impl LinuxFeed {
+    /// Creates a feed for the official NVIDIA download server.
+    pub fn new() -> LinuxFeed {
+        LinuxFeed::default()
+    }
+
+    /// Sets the base URL of the driver index. A trailing slash is added if
+    /// missing.
+    pub fn base_url(mut self, url: &str) -> LinuxFeed {
+        self.base_url = url.to_string();
+        if !self.base_url.ends_with('/') {
+            self.base_url.push('/');
+        }
+        self
+    }
+
+    /// Follows a single driver branch, e.g. 535.
+    pub fn branch(mut self, major: u32) -> LinuxFeed {
+        self.branch = Some(major);
+        self
+    }
+
+    /// Returns the URL of the file naming the latest driver.
+    pub fn latest_url(&self) -> String {
+        format!("{}latest.txt", self.base_url)
+    }
+
+    /// Returns the download URL of the .run installer of the given version.
+    pub fn installer_url(&self, version: &DriverVersion) -> String {
+        format!(
+            "{}{version}/NVIDIA-Linux-x86_64-{version}.run",
+            self.base_url
+        )
+    }
+}
+
+/// Parses the contents of latest.txt, e.g.
+/// "550.127.05 550.127.05/NVIDIA-Linux-x86_64-550.127.05.run". The installer
+/// path is relative to the base URL of the feed.
+pub fn parse_latest_txt(
+    contents: &str,
+    feed: &LinuxFeed,
+) -> Result<(DriverVersion, String), DriverCheckError> {
+    let mut fields = contents.split_whitespace();
+    let version = fields
+        .next()
+        .ok_or(DriverCheckError::MissingField("version"))?
+        .parse()?;
+    let path = fields
+        .next()
+        .ok_or(DriverCheckError::MissingField("download URL"))?;
+    Ok((version, format!("{}{path}", feed.base_url)))
+}
+
+/// Parses the driver versions from the directory listing of the driver
+/// index. The versions are returned in the order they are listed.
+pub fn parse_branch_listing(html: &str) -> Vec<DriverVersion> {
+    let pattern = Regex::new(r#"href=['"]?([0-9]+\.[0-9]+(?:\.[0-9]+)?)/"#).unwrap();
+    pattern
+        .captures_iter(html)
+        .filter_map(|captures| captures[1].parse().ok())
+        .collect()
+}
+
+/// Retrieves the latest available Linux driver version and the download URL
+/// of its .run installer as a tuple.
+///
+/// Takes as an argument a fetcher that is able to retrieve data from the
+/// server. Just use get_page() here.
+///
+/// If the information cannot be retrieved, then an error is provided as a
+/// result.
+pub fn get_linux_version_information(
+    fetcher: &impl HttpFetcher,
+    feed: &LinuxFeed,
+) -> Result<(DriverVersion, String), DriverCheckError> {
+    match feed.branch {
+        None => parse_latest_txt(&fetcher.fetch(&feed.latest_url())?, feed),
+        Some(_) => {
+            let release = list_linux_drivers(fetcher, feed)?
+                .into_iter()
+                .next()
+                .ok_or(DriverCheckError::MissingField("version"))?;
+            Ok((release.version, release.download_url))
+        }
+    }
+}
+
+/// Retrieves every Linux driver release of the driver index, newest first.
+/// If the feed follows a branch, then only the releases of that branch are
+/// returned.
+///
+/// Takes as an argument a fetcher that is able to retrieve data from the
+/// server. Just use get_page() here.
+pub fn list_linux_drivers(
+    fetcher: &impl HttpFetcher,
+    feed: &LinuxFeed,
+) -> Result<Vec<DriverRelease>, DriverCheckError> {
+    let mut versions: Vec<DriverVersion> = parse_branch_listing(&fetcher.fetch(&feed.base_url)?)
+        .into_iter()
+        .filter(|version| feed.branch.is_none() || feed.branch == Some(version.major))
+        .collect();
+    versions.sort_by(|a, b| b.cmp(a));
+    Ok(versions
+        .iter()
+        .map(|version| DriverRelease::new(*version, &feed.installer_url(version)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_page;
+    use crate::test_server::TestServer;
+
+    /// Starts a stand-in for the NVIDIA download server.
+    fn start_server() -> TestServer {
+        TestServer::serve(&[
+            ("/latest.txt", include_str!("../fixtures/linux_latest.txt")),
+            ("/", include_str!("../fixtures/linux_listing.html")),
+        ])
+    }
+
+    /// Test that latest.txt is parsed.
+    #[test]
+    fn parse_latest_txt_success() {
+        let (version, url) = parse_latest_txt(
+            include_str!("../fixtures/linux_latest.txt"),
+            &LinuxFeed::new(),
+        )
+        .unwrap();
+        assert_eq!(version.to_string(), "550.127.05");
+        assert_eq!(
+            url,
+            "https://download.nvidia.com/XFree86/Linux-x86_64/550.127.05/NVIDIA-Linux-x86_64-550.127.05.run"
+        );
+    }
+
+    /// Test that incomplete latest.txt is reported.
+    #[test]
+    fn parse_latest_txt_fail() {
+        let feed = LinuxFeed::new();
+        assert!(matches!(
+            parse_latest_txt("", &feed),
+            Err(DriverCheckError::MissingField("version"))
+        ));
+        assert!(matches!(
+            parse_latest_txt("550.127.05", &feed),
+            Err(DriverCheckError::MissingField("download URL"))
+        ));
+        assert!(matches!(
+            parse_latest_txt("<html>", &feed),
+            Err(DriverCheckError::ParseVersion(_))
+        ));
+    }
+
+    /// Test that the versions are parsed from the directory listing.
+    #[test]
+    fn parse_branch_listing_success() {
+        let versions = parse_branch_listing(include_str!("../fixtures/linux_listing.html"));
+        let versions: Vec<String> = versions.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            versions,
+            [
+                "535.183.01",
+                "535.216.01",
+                "550.120",
+                "550.127.05",
+                "560.35.03"
+            ]
+        );
+    }
+
+    /// Test that the latest driver is fetched from the server.
+    #[test]
+    fn get_linux_version_information_latest() {
+        let server = start_server();
+        let feed = LinuxFeed::new().base_url(&server.url);
+        let (version, url) = get_linux_version_information(&get_page, &feed).unwrap();
+        assert_eq!(version.to_string(), "550.127.05");
+        assert_eq!(
+            url,
+            format!(
+                "{}550.127.05/NVIDIA-Linux-x86_64-550.127.05.run",
+                server.url
+            )
+        );
+        assert_eq!(server.requests()[0].path, "/latest.txt");
+    }
+
+    /// Test that every release is listed newest first.
+    #[test]
+    fn list_linux_drivers_success() {
+        let server = start_server();
+        let feed = LinuxFeed::new().base_url(&server.url);
+        let releases = list_linux_drivers(&get_page, &feed).unwrap();
+        assert_eq!(releases.len(), 5);
+        assert_eq!(releases[0].version.to_string(), "560.35.03");
+        assert_eq!(releases[4].version.to_string(), "535.183.01");
+        let releases = list_linux_drivers(&get_page, &feed.branch(550)).unwrap();
+        let versions: Vec<String> = releases.iter().map(|r| r.version.to_string()).collect();
+        assert_eq!(versions, ["550.127.05", "550.120"]);
+    }
+
+    /// Test that the newest driver of the branch is fetched from the server.
+    #[test]
+    fn get_linux_version_information_branch() {
+        let server = start_server();
+        let feed = LinuxFeed::new().base_url(&server.url).branch(535);
+        let (version, url) = get_linux_version_information(&get_page, &feed).unwrap();
+        assert_eq!(version.to_string(), "535.216.01");
+        assert!(url.ends_with("/535.216.01/NVIDIA-Linux-x86_64-535.216.01.run"));
+        let feed = feed.branch(470);
+        assert!(matches!(
+            get_linux_version_information(&get_page, &feed),
+            Err(DriverCheckError::MissingField("version"))
+        ));
+    }
+}
@@ -1,56 +1,1032 @@
+use clap::builder::BoolishValueParser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use geforcedrvchk3::{
-    ask_confirmation, get_available_version_information, get_installed_version, get_page,
-    start_browser, SMI, VERSION,
+    ask_confirmation, confirm_installed_version, detect_channel, download_file,
+    find_driver_mismatches, format_age, format_file_size, get_installed_gpus,
+    get_installed_version, get_page, latest_stable_and_beta, start_browser, verify_installer,
+    Catalog, Config, DefaultAction, DriverChannel, DriverCheckError, DriverQuery, DriverRelease,
+    DriverSource, DriverVersion, GpuInfo, HttpClient, HttpFetcher, InstallOutcome, LinuxFeed,
+    LinuxFeedSource, MemoFetcher, MirrorRelease, MirrorSource, MirrorSync, NvidiaApiSource,
+    ReleaseNotes, ResponseCache, Retention, SilentInstall, UpdateReport, MANIFEST_FILE, SMI,
+    VERSION,
 };
-use std::io::{stdin, stdout, Write};
+use std::error::Error;
+use std::io::{stderr, stdin, stdout, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-fn handle_error<T>(result: Result<T, &'static str>) -> T {
+/// Returns the process exit code for the error, so that scripts can tell
+/// the failure kinds apart.
+fn exit_code(error: &DriverCheckError) -> i32 {
+    match error {
+        DriverCheckError::Network(_) => 2,
+        DriverCheckError::InvalidUtf8(_) => 3,
+        DriverCheckError::MalformedJson(_)
+        | DriverCheckError::MalformedXml(_)
+        | DriverCheckError::MalformedManifest(_)
+        | DriverCheckError::ParseDate(_) => 4,
+        DriverCheckError::MissingField(_) => 5,
+        DriverCheckError::SmiNotFound => 6,
+        DriverCheckError::SmiFailed(_) => 7,
+        DriverCheckError::SmiOutput => 8,
+        DriverCheckError::ParseVersion(_) => 9,
+        DriverCheckError::DriverFile(_) => 11,
+        DriverCheckError::BrowserLaunch(_) => 12,
+        DriverCheckError::UnknownChannel(_) | DriverCheckError::UnknownProduct(_) => 13,
+        DriverCheckError::Download(_) | DriverCheckError::DownloadInterrupted(_) => 14,
+        DriverCheckError::DownloadFile { .. } => 15,
+        DriverCheckError::SizeMismatch { .. } | DriverCheckError::ChecksumMismatch { .. } => 16,
+        DriverCheckError::InstallerFile { .. } => 17,
+        DriverCheckError::InvalidChecksum(_) => 18,
+        DriverCheckError::InstallerLaunch { .. } => 19,
+        DriverCheckError::InstallerExit(_) => 20,
+        DriverCheckError::InstallNotConfirmed { .. } => 21,
+        DriverCheckError::InstallLog { .. } => 22,
+        DriverCheckError::ConfigFile { .. } | DriverCheckError::Config { .. } => 23,
+        DriverCheckError::HttpClient(_)
+        | DriverCheckError::UserAgent(_)
+        | DriverCheckError::CaBundle { .. } => 24,
+        DriverCheckError::NotCached(_) => 25,
+        DriverCheckError::ManifestFile { .. } | DriverCheckError::MirrorNotSet => 26,
+        DriverCheckError::ReleaseNotFound(_) => 27,
+    }
+}
+
+/// Set with --quiet and --json, suppresses the informational output.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Cleared with --no-pause, or if the input is not a terminal, so that a
+/// failing scheduled task does not wait for Enter forever.
+static PAUSE: AtomicBool = AtomicBool::new(true);
+
+/// Prints informational output, unless --quiet or --json is given.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Exit code telling that a driver update is available.
+const UPDATE_AVAILABLE: i32 = 10;
+
+/// Number of releases searched for an older driver version, e.g. for its
+/// release notes or for the size of its installer.
+const LOOKUP_RESULTS: u32 = 100;
+
+/// Checks NVIDIA display driver updates.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Only check for an update, never ask anything or download anything
+    #[arg(long)]
+    check_only: bool,
+
+    /// Answer every question with the default answer
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Exit right away after an error instead of waiting for Enter
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    no_pause: Option<bool>,
+
+    /// Download the new driver without asking
+    #[arg(long, conflicts_with_all = ["check_only", "open_browser"])]
+    download: bool,
+
+    /// Open the download of the new driver in the browser without asking
+    #[arg(long, conflicts_with = "check_only")]
+    open_browser: bool,
+
+    /// Print only the errors
+    #[arg(short, long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quiet: Option<bool>,
+
+    /// Print the result as JSON instead of text, same as --format json
+    #[arg(long, conflicts_with = "format")]
+    json: bool,
+
+    /// Format of the result, json and csv imply --check-only unless
+    /// --download or --open-browser is given
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Source of the driver information [default: nvidia under Windows,
+    /// linux elsewhere]
+    #[arg(long, env = "GEFORCEDRVCHK3_SOURCE", value_enum)]
+    source: Option<SourceKind>,
+
+    /// Base URL or directory of a local driver mirror with a manifest.json,
+    /// or the URL or path of the manifest itself. Implies --source mirror
+    /// unless another source is given
+    #[arg(long, env = "GEFORCEDRVCHK3_MIRROR")]
+    mirror: Option<String>,
+
+    /// Driver channel, e.g. "studio" [default: the channel of the installed
+    /// driver]
+    #[arg(long, env = "GEFORCEDRVCHK3_CHANNEL")]
+    channel: Option<DriverChannel>,
+
+    /// Look for beta and hotfix drivers too
+    #[arg(
+        long,
+        env = "GEFORCEDRVCHK3_BETA",
+        value_parser = BoolishValueParser::new(),
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    beta: Option<bool>,
+
+    /// Directory the drivers are downloaded to [default: the current
+    /// directory]
+    #[arg(long, env = "GEFORCEDRVCHK3_DOWNLOAD_DIR")]
+    download_dir: Option<PathBuf>,
+
+    /// Answer from the cached lookup responses without accessing the
+    /// network, implies --check-only unless --open-browser is given
+    #[arg(
+        long,
+        env = "GEFORCEDRVCHK3_OFFLINE",
+        value_parser = BoolishValueParser::new(),
+        conflicts_with = "download"
+    )]
+    offline: bool,
+
+    /// Install the downloaded driver unattended
+    #[arg(
+        long,
+        env = "GEFORCEDRVCHK3_INSTALL",
+        value_parser = BoolishValueParser::new(),
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    install: Option<bool>,
+
+    /// Installer switches replacing the default ones, e.g. "-s -noreboot"
+    #[arg(long, env = "GEFORCEDRVCHK3_INSTALL_ARGS", allow_hyphen_values = true)]
+    install_args: Option<String>,
+
+    /// Configuration file [default: geforcedrvchk3/config.toml in the
+    /// per-user configuration directory]
+    #[arg(long, env = "GEFORCEDRVCHK3_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Settings of the configuration file, used for the options not given on
+    /// the command line or in the environment variables.
+    #[arg(skip)]
+    settings: Config,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Verify an already downloaded driver installer
+    Verify {
+        /// Path of the installer
+        installer: PathBuf,
+        /// Expected SHA-256 checksum [default: the checksum in
+        /// <installer>.sha256, if there is one]
+        sha256: Option<String>,
+    },
+    /// Download the drivers found with the queries to a mirror directory
+    /// and write the manifest of the mirror, see --mirror
+    Mirror {
+        /// Mirror directory
+        directory: PathBuf,
+        /// Driver queries, i.e. product names with an optional channel, e.g.
+        /// "GeForce RTX 4070" or "GeForce RTX 4070:studio" [default: the
+        /// configured or the detected product]
+        queries: Vec<String>,
+        /// Number of the newest releases kept per channel, product and
+        /// operating system, counting the stable and the beta releases
+        /// separately
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+        keep: u64,
+        /// Prune the releases older than this many days, except the newest
+        /// release of each channel
+        #[arg(long, value_name = "DAYS")]
+        max_age: Option<u32>,
+    },
+    /// Show the release notes of a driver version as plain text
+    Notes {
+        /// Driver version, e.g. 566.14 [default: the latest driver]
+        version: Option<DriverVersion>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text
+    Text,
+    /// A single JSON document
+    Json,
+    /// A CSV header line and a single record
+    Csv,
+}
+
+impl Cli {
+    /// Rejects --install with --offline. Unlike the other conflicts, this one
+    /// is checked after parsing, because --install=false and
+    /// GEFORCEDRVCHK3_INSTALL=0 do not conflict.
+    fn check_conflicts(self) -> Result<Cli, clap::Error> {
+        if self.offline && self.install == Some(true) {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "the argument '--offline' cannot be used with '--install'",
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Loads the configuration file and fills in the options not given on
+    /// the command line or in the environment variables.
+    fn load_config(self) -> Cli {
+        let settings = handle_error(Config::load(self.config.as_deref()));
+        self.with_settings(settings)
+    }
+
+    /// Fills in the options not given on the command line or in the
+    /// environment variables from the settings.
+    fn with_settings(mut self, settings: Config) -> Cli {
+        self.channel = self.channel.or(settings.channel);
+        self.beta = self.beta.or(Some(settings.beta));
+        self.install = self.install.or(Some(settings.install));
+        self.quiet = self.quiet.or(Some(settings.notifications.quiet));
+        self.no_pause = self.no_pause.or(Some(!settings.notifications.pause));
+        if self.download_dir.is_none() {
+            self.download_dir.clone_from(&settings.download_dir);
+        }
+        if self.mirror.is_none() {
+            self.mirror.clone_from(&settings.mirror);
+        }
+        self.settings = settings;
+        self
+    }
+
+    /// Tells whether the beta drivers are looked for.
+    fn beta(&self) -> bool {
+        self.beta.unwrap_or(false)
+    }
+
+    /// Tells whether the downloaded driver is installed.
+    fn install(&self) -> bool {
+        self.install.unwrap_or(false)
+    }
+
+    /// Tells whether only the errors are printed.
+    fn quiet(&self) -> bool {
+        self.quiet.unwrap_or(false)
+    }
+
+    /// Tells whether the program exits right away after an error.
+    fn no_pause(&self) -> bool {
+        self.no_pause.unwrap_or(false)
+    }
+
+    /// Returns the name or the path of nvidia-smi.
+    fn smi(&self) -> &str {
+        self.settings.smi.as_deref().unwrap_or(SMI)
+    }
+
+    /// Returns the download directory.
+    fn download_dir(&self) -> &Path {
+        self.download_dir.as_deref().unwrap_or(Path::new("."))
+    }
+
+    /// Returns the installer switches, if the default ones are replaced.
+    fn install_args(&self) -> Option<Vec<String>> {
+        match &self.install_args {
+            Some(args) => Some(args.split_whitespace().map(String::from).collect()),
+            None => self.settings.install_args.clone(),
+        }
+    }
+
+    /// Returns the default answer of the question, whose options are in the
+    /// download, open in the browser and quit order.
+    fn default_answer(&self, options: [Option<usize>; 3]) -> usize {
+        let [download, open, quit] = options;
+        let answer = match self.settings.notifications.default_action {
+            DefaultAction::Download => download,
+            DefaultAction::OpenBrowser => open,
+            DefaultAction::Quit => quit,
+        };
+        answer.or(quit).unwrap_or(0)
+    }
+
+    /// Makes a client with the network and proxy settings of the
+    /// configuration file the shared client for the online resources.
+    fn configure_http(&self) -> Result<(), DriverCheckError> {
+        HttpClient::new(&self.settings.http_config())?.make_shared();
+        Ok(())
+    }
+
+    /// Returns the output format, taking --json into account.
+    fn output_format(&self) -> OutputFormat {
+        if self.json {
+            OutputFormat::Json
+        } else {
+            self.format
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SourceKind {
+    /// The NVIDIA driver lookup service
+    Nvidia,
+    /// The NVIDIA Unix driver index
+    Linux,
+    /// A local driver mirror, see --mirror
+    Mirror,
+}
+
+/// Parses the command line. Invalid command lines exit with code 1, so
+/// that they are not mixed up with the network errors.
+fn parse_cli() -> Cli {
+    Cli::try_parse()
+        .and_then(Cli::check_conflicts)
+        .unwrap_or_else(|err| {
+            let _ = err.print();
+            std::process::exit(if err.use_stderr() { 1 } else { 0 });
+        })
+}
+
+fn handle_error<T>(result: Result<T, DriverCheckError>) -> T {
     let mut input = String::new();
 
     match result {
         Ok(value) => value,
         Err(value) => {
-            println!("{value}");
-            print!("\nPress Enter...");
-            stdout().flush().unwrap();
-            stdin().read_line(&mut input).unwrap();
-            std::process::exit(1);
+            eprintln!("{value}");
+            let mut source = value.source();
+            while let Some(cause) = source {
+                eprintln!("  Caused by: {cause}");
+                source = cause.source();
+            }
+            if PAUSE.load(Ordering::Relaxed) {
+                eprint!("\nPress Enter...");
+                stderr().flush().unwrap();
+                stdin().read_line(&mut input).unwrap();
+            }
+            std::process::exit(exit_code(&value));
         }
     }
 }
 
-fn main() {
-    println!("Display Driver Check version {VERSION}");
+/// Builds the driver query for the installed GPUs. The first GPU found from
+/// the catalog is used. Falls back to the default query if no GPU can be
+/// detected or none of them is found from the catalog.
+fn detect_query(gpus: &[GpuInfo]) -> DriverQuery {
+    let catalog = Catalog::bundled();
+    for gpu in gpus {
+        info!("Detected graphics card:             {}", gpu.name);
+    }
+    match gpus.iter().find_map(|gpu| catalog.find_product(&gpu.name)) {
+        Some(product) => DriverQuery::new().product(&product),
+        None => {
+            if !gpus.is_empty() {
+                info!("Unknown graphics card, using the default driver.");
+            }
+            DriverQuery::new()
+        }
+    }
+}
+
+/// Selects the release channel matching the installed driver, so that the
+/// installed driver is compared with the drivers of the same channel, unless
+/// the channel is chosen with --channel. The first candidate channel is used
+/// if the installed version is not found from any of them.
+fn detect_query_channel(
+    cli: &Cli,
+    fetcher: &impl HttpFetcher,
+    query: DriverQuery,
+    gpus: &[GpuInfo],
+    installed: &DriverVersion,
+) -> Result<DriverQuery, DriverCheckError> {
+    if let Some(channel) = cli.channel {
+        return Ok(query.channel(channel));
+    }
+    let candidates = DriverChannel::for_gpu_name(gpus.first().map_or("GeForce", |gpu| &gpu.name));
+    let channel = detect_channel(fetcher, &query, installed, &candidates)?;
+    Ok(query.channel(channel.unwrap_or(candidates[0])))
+}
+
+/// Opens the configured response cache. Offline mode fails without a cache,
+/// as there cannot be any cached responses.
+fn open_cache(cli: &Cli) -> Result<Option<ResponseCache>, DriverCheckError> {
+    let cache = cli.settings.response_cache(cli.offline);
+    if cli.offline && cache.is_none() {
+        return Err(DriverCheckError::NotCached("the driver lookup".to_string()));
+    }
+    Ok(cache)
+}
 
-    let installed: String = handle_error(get_installed_version(SMI));
-    let available: (String, String) = handle_error(get_available_version_information(get_page));
+/// Selects the source of the available driver information. By default the
+/// source matching the operating system is used.
+///
+/// The release channel is returned for the sources having channels.
+fn select_source<'a>(
+    cli: &Cli,
+    cache: Option<&'a ResponseCache>,
+    gpus: &[GpuInfo],
+    installed: &DriverVersion,
+) -> Result<(Box<dyn DriverSource + 'a>, Option<DriverChannel>), DriverCheckError> {
+    let fetcher = move |url: &str| match cache {
+        Some(cache) => cache.fetch(url),
+        None => get_page(url),
+    };
+    let default = if cli.mirror.is_some() {
+        SourceKind::Mirror
+    } else if cfg!(windows) {
+        SourceKind::Nvidia
+    } else {
+        SourceKind::Linux
+    };
+    match cli.source.unwrap_or(default) {
+        SourceKind::Mirror => {
+            let location = cli
+                .mirror
+                .as_deref()
+                .ok_or(DriverCheckError::MirrorNotSet)?;
+            let mut source = MirrorSource::new(fetcher, location);
+            if let Some(channel) = cli.channel {
+                source = source.channel(channel);
+            }
+            // Without a detected or configured product or operating system,
+            // the releases of all of them are used.
+            let query = cli.settings.apply_query(detect_query(gpus));
+            let catalog = Catalog::bundled();
+            let settings = &cli.settings.query;
+            if settings.product.is_some()
+                || settings.product_family.is_some()
+                || gpus
+                    .iter()
+                    .any(|gpu| catalog.find_product(&gpu.name).is_some())
+            {
+                source = source.product_family(query.product_family);
+            }
+            if settings.os.is_some() {
+                source = source.os(query.os);
+            }
+            Ok((Box::new(source), cli.channel))
+        }
+        SourceKind::Linux => Ok((
+            Box::new(LinuxFeedSource::new(fetcher, LinuxFeed::new())),
+            None,
+        )),
+        SourceKind::Nvidia => {
+            let query = cli.settings.apply_query(detect_query(gpus));
+            let query = if matches!(
+                cli.command,
+                Some(Command::Notes { .. } | Command::Verify { .. })
+            ) {
+                // An older release may be looked up, beta or not.
+                query.beta(true).number_of_results(LOOKUP_RESULTS)
+            } else {
+                query.beta(cli.beta())
+            };
+            // The lookup of the detected channel is the lookup of the source.
+            let fetcher = MemoFetcher::new(fetcher);
+            let query = detect_query_channel(cli, &fetcher, query, gpus, installed)?;
+            let channel = query.channel;
+            Ok((
+                Box::new(NvidiaApiSource::new(fetcher, query)),
+                Some(channel),
+            ))
+        }
+    }
+}
+
+/// Prints the version, release date and download size of the release.
+fn print_release(label: &str, release: &DriverRelease) {
+    info!("{label:<36}{}", release.version);
+    if let Some(date) = release.release_date {
+        info!("Release date:                       {date}");
+    }
+    if let Some(size) = release.file_size {
+        info!(
+            "Download size:                      {}",
+            format_file_size(size)
+        );
+    }
+}
+
+/// Asks the question, or answers it with the default answer with --yes.
+fn confirm(cli: &Cli, message: &str, options: &[char], default: usize) -> usize {
+    if cli.yes {
+        default
+    } else {
+        ask_confirmation(message, options, default)
+    }
+}
 
-    let instd_ver: f64 = handle_error(
-        installed
-            .parse()
-            .or(Err("Cannot convert installed version number!")),
+/// Downloads the installation package of the release with a progress bar
+/// to the download directory. Returns true if the driver was installed too.
+fn download_driver(cli: &Cli, release: &DriverRelease) -> bool {
+    let mut shown = None;
+    let path = handle_error(download_file(
+        &release.download_url,
+        cli.download_dir(),
+        |progress| {
+            let percent = progress.percent();
+            if !QUIET.load(Ordering::Relaxed) && (percent.is_none() || percent != shown) {
+                shown = percent;
+                print!("\r{progress}");
+                stdout().flush().unwrap();
+            }
+        },
+    ));
+    info!("\nDriver downloaded to {}", path.display());
+    let sha256 = handle_error(verify_installer(
+        &path,
+        release.expected_size(),
+        cli.settings
+            .checksum(&release.version)
+            .or(release.sha256.as_deref()),
+    ));
+    info!("SHA-256 checksum:                   {sha256}");
+    if cli.install() {
+        install_driver(cli, &path, &release.version);
+    }
+    cli.install()
+}
+
+/// Installs the downloaded driver unattended and confirms that the new
+/// version is in use, or that it is used after a pending reboot. The output
+/// of the installer is logged next to the installer.
+fn install_driver(cli: &Cli, path: &Path, version: &DriverVersion) {
+    let mut log_file = path.as_os_str().to_owned();
+    log_file.push(".log");
+    let mut install = SilentInstall::new().log_file(log_file);
+    if let Some(args) = cli.install_args() {
+        install = install.args(args);
+    }
+    info!("Installing driver version {version}...");
+    let output = handle_error(install.install(path));
+    let reboot_pending = install.reboot_pending(path, &output);
+    let outcome = confirm_installed_version(cli.smi(), version, reboot_pending);
+    match handle_error(outcome) {
+        InstallOutcome::Installed(installed) => {
+            info!("Driver version {installed} installed successfully.");
+        }
+        InstallOutcome::RebootRequired => {
+            info!("Driver version {version} installed, a reboot is required to use it.");
+        }
+    }
+}
+
+/// Returns the driver version from the installer file name, e.g.
+/// "566.14-desktop-win10-win11-64bit-international-dch-whql.exe" or
+/// "NVIDIA-Linux-x86_64-550.54.14.run".
+fn installer_version(path: &Path) -> Option<DriverVersion> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.strip_suffix(".exe").or(name.strip_suffix(".run"))?;
+    stem.split('-').find_map(|part| part.parse().ok())
+}
+
+/// Looks up the releases from the selected source for a subcommand working on
+/// a given driver version, which may be an older release or a beta.
+///
+/// The installed version only helps to detect the channel, so that the
+/// subcommands work without a driver too.
+fn lookup_releases(cli: &Cli) -> Result<Vec<DriverRelease>, DriverCheckError> {
+    let installed = get_installed_version(cli.smi()).unwrap_or(DriverVersion::new(0, 0));
+    let gpus = get_installed_gpus(cli.smi()).unwrap_or_default();
+    let cache = open_cache(cli)?;
+    let (source, _) = select_source(cli, cache.as_ref(), &gpus, &installed)?;
+    source.releases()
+}
+
+/// Re-checks an already downloaded installer. The expected size is looked
+/// up from the releases of the selected source.
+fn verify_command(cli: &Cli, path: &Path, sha256: Option<&str>) {
+    let version = installer_version(path);
+    let sha256 = sha256.or_else(|| cli.settings.checksum(&version?));
+    let expected_size = version.and_then(|version| {
+        let releases = lookup_releases(cli).unwrap_or_default();
+        let size = releases
+            .into_iter()
+            .find(|release| release.version == version)
+            .and_then(|release| release.expected_size());
+        if size.is_none() {
+            info!("Size of driver version {version} is unknown, not checking the size.");
+        }
+        size
+    });
+    let sha256 = handle_error(verify_installer(path, expected_size, sha256));
+    info!("{} is OK", path.display());
+    info!("SHA-256 checksum: {sha256}");
+}
+
+/// Parses a mirror query, i.e. a product name with an optional ":channel"
+/// suffix. The other lookup parameters are taken from the base query.
+fn mirror_query(
+    cli: &Cli,
+    base: &DriverQuery,
+    spec: &str,
+) -> Result<DriverQuery, DriverCheckError> {
+    let (product, channel) = match spec.rsplit_once(':') {
+        Some((product, channel)) => (product, Some(channel.parse()?)),
+        None => (spec, None),
+    };
+    let mut query = base.clone().beta(cli.beta());
+    if !product.trim().is_empty() {
+        let product = Catalog::bundled()
+            .find_product(product)
+            .ok_or_else(|| DriverCheckError::UnknownProduct(product.to_string()))?;
+        query = query.product(&product);
+    }
+    match channel.or(cli.channel) {
+        Some(channel) => Ok(query.channel(channel)),
+        None => Ok(query),
+    }
+}
+
+/// Downloads the drivers found with the queries to the mirror directory and
+/// prunes the old ones.
+fn mirror_command(cli: &Cli, directory: &Path, specs: &[String], retention: Retention) {
+    let gpus = get_installed_gpus(cli.smi()).unwrap_or_default();
+    let base = cli.settings.apply_query(detect_query(&gpus));
+    let specs = if specs.is_empty() {
+        vec![String::new()]
+    } else {
+        specs.to_vec()
+    };
+    let mut sync = MirrorSync::new(get_page, directory).retention(retention);
+    for spec in &specs {
+        sync = sync.query(handle_error(mirror_query(cli, &base, spec)));
+    }
+
+    let mut shown: Option<(String, Option<u8>)> = None;
+    let update = handle_error(sync.run(|release, progress| {
+        let url = release.source_url.clone().unwrap_or_default();
+        let percent = progress.percent();
+        if QUIET.load(Ordering::Relaxed)
+            || (percent.is_some() && shown == Some((url.clone(), percent)))
+        {
+            return;
+        }
+        if shown
+            .as_ref()
+            .is_some_and(|(shown_url, _)| *shown_url != url)
+        {
+            println!();
+        }
+        print!("\r{:<12}{progress}", release.version);
+        stdout().flush().unwrap();
+        shown = Some((url, percent));
+    }));
+    if shown.is_some() {
+        info!("");
+    }
+
+    let describe = |release: &MirrorRelease| match release.channel {
+        Some(channel) => format!("{} ({channel})", release.version),
+        None => release.version.to_string(),
+    };
+    for release in &update.added {
+        info!("Added driver version {}", describe(release));
+    }
+    for release in &update.pruned {
+        info!("Pruned driver version {}", describe(release));
+    }
+    info!(
+        "Mirror of {} releases written to {}",
+        update.manifest.releases.len(),
+        directory.join(MANIFEST_FILE).display()
     );
-    let avail_ver: f64 = handle_error(
-        available
+}
+
+/// Shows the release notes of the driver version, or of the latest driver
+/// of the channel.
+fn notes_command(cli: &Cli, version: Option<DriverVersion>) {
+    let releases = handle_error(lookup_releases(cli));
+    let release = match version {
+        Some(version) => releases
+            .iter()
+            .find(|release| release.version == version)
+            .ok_or(DriverCheckError::ReleaseNotFound(version)),
+        // The lookup includes the betas, but a beta is the latest driver
+        // only with --beta.
+        None if cli.beta() => releases
+            .iter()
+            .max_by_key(|release| release.version)
+            .ok_or(DriverCheckError::MissingField("version")),
+        None => latest_stable_and_beta(&releases)
             .0
-            .parse()
-            .or(Err("Cannot convert available version number!")),
+            .ok_or(DriverCheckError::MissingField("version")),
+    };
+    let release = handle_error(release);
+    info!("");
+    println!("{}", ReleaseNotes::from(release));
+}
+
+fn main() {
+    let cli = parse_cli();
+    PAUSE.store(!cli.no_pause() && stdin().is_terminal(), Ordering::Relaxed);
+    let cli = cli.load_config();
+    if cli.no_pause() {
+        // The configuration file may turn the pause off.
+        PAUSE.store(false, Ordering::Relaxed);
+    }
+    handle_error(cli.configure_http());
+    let format = cli.output_format();
+    QUIET.store(
+        cli.quiet() || format != OutputFormat::Text,
+        Ordering::Relaxed,
     );
-    let avail_url: String = available.1;
 
-    println!("Currently installed driver version: {instd_ver}");
+    info!("Display Driver Check version {VERSION}");
+
+    match &cli.command {
+        Some(Command::Verify { installer, sha256 }) => {
+            verify_command(&cli, installer, sha256.as_deref());
+            return;
+        }
+        Some(Command::Mirror {
+            directory,
+            queries,
+            keep,
+            max_age,
+        }) => {
+            let retention = Retention {
+                keep: *keep as usize,
+                max_age_days: *max_age,
+            };
+            mirror_command(&cli, directory, queries, retention);
+            return;
+        }
+        Some(Command::Notes { version }) => {
+            notes_command(&cli, *version);
+            return;
+        }
+        None => {}
+    }
+
+    let instd_ver: DriverVersion = handle_error(get_installed_version(cli.smi()));
+    let gpus = get_installed_gpus(cli.smi()).unwrap_or_default();
+    for mismatch in find_driver_mismatches(&gpus) {
+        info!("Warning: {mismatch}");
+    }
+    let cache = handle_error(open_cache(&cli));
+    let (source, channel) = handle_error(select_source(&cli, cache.as_ref(), &gpus, &instd_ver));
+    let (available, beta): (Option<DriverRelease>, Option<DriverRelease>) = if cli.beta() {
+        let releases = handle_error(source.releases());
+        if releases.is_empty() {
+            handle_error::<()>(Err(DriverCheckError::MissingField("version")));
+        }
+        let (stable, beta) = latest_stable_and_beta(&releases);
+        (stable.cloned(), beta.cloned())
+    } else {
+        (Some(handle_error(source.latest())), None)
+    };
+
+    if let Some(age) = cache.as_ref().and_then(ResponseCache::cached_age) {
+        let age = format_age(age);
+        if cli.offline {
+            info!("Offline, using the driver information cached {age} ago.");
+        } else {
+            info!("Using the driver information cached {age} ago.");
+        }
+    }
+    match channel {
+        Some(channel) => info!("Currently installed driver version: {instd_ver} ({channel})"),
+        None => info!("Currently installed driver version: {instd_ver}"),
+    }
+
+    let report = UpdateReport::new(instd_ver, channel, &gpus, available.as_ref(), beta.as_ref());
+    match format {
+        OutputFormat::Json => println!("{}", report.to_json()),
+        OutputFormat::Csv => print!("{}", report.to_csv()),
+        OutputFormat::Text => {}
+    }
+
+    // With --beta the releases may include no stable release at all.
+    let has_stable = available.is_some();
+    let available = available.filter(|release| instd_ver < release.version);
+    // A beta is only worth offering if it is newer than the stable driver.
+    let beta = beta.filter(|beta| {
+        instd_ver < beta.version
+            && available
+                .as_ref()
+                .is_none_or(|stable| stable.version < beta.version)
+    });
+
+    let Some(newest) = available.as_ref().or(beta.as_ref()) else {
+        return;
+    };
 
-    if instd_ver < avail_ver {
-        println!("New driver version is available:    {avail_ver}\n");
-        match ask_confirmation(
-            "Do you want to \
-                                (d)ownload the latest driver, or \
+    if !has_stable {
+        info!("No stable driver is available.");
+    } else if available.is_none() {
+        info!("The latest stable driver is installed.");
+    }
+    match (&available, &beta) {
+        (Some(available), None) => print_release("New driver version is available:", available),
+        _ => {
+            if let Some(available) = &available {
+                print_release("Latest stable driver version:", available);
+            }
+            if let Some(beta) = &beta {
+                print_release("Latest beta driver version:", beta);
+            }
+        }
+    }
+
+    let interactive = !cli.check_only && !cli.offline && format == OutputFormat::Text;
+    let installed = if cli.download {
+        download_driver(&cli, newest)
+    } else if cli.open_browser {
+        handle_error(start_browser(&newest.download_url));
+        false
+    } else if !interactive {
+        false
+    } else {
+        info!("");
+        match (&available, &beta) {
+            (Some(available), None) => match confirm(
+                &cli,
+                "Do you want to \
+                                (d)ownload the latest driver, \
+                                (o)pen it in the browser, or \
+                                (q)uit?",
+                &['d', 'o', 'q'],
+                cli.default_answer([Some(0), Some(1), Some(2)]),
+            ) {
+                0 => download_driver(&cli, available),
+                1 => {
+                    handle_error(start_browser(&available.download_url));
+                    false
+                }
+                _ => false,
+            },
+            (Some(available), Some(beta)) => match confirm(
+                &cli,
+                "Do you want to download the latest \
+                                (s)table driver, the latest \
+                                (b)eta driver, or \
                                 (q)uit?",
-            &vec!['d', 'q'],
-            0,
-        ) {
-            0 => start_browser(&avail_url),
-            _ => (),
+                &['s', 'b', 'q'],
+                cli.default_answer([Some(0), None, Some(2)]),
+            ) {
+                0 => download_driver(&cli, available),
+                1 => download_driver(&cli, beta),
+                _ => false,
+            },
+            (None, Some(beta)) => {
+                confirm(
+                    &cli,
+                    "Do you want to download the \
+                                (b)eta driver, or \
+                                (q)uit?",
+                    &['b', 'q'],
+                    1,
+                ) == 0
+                    && download_driver(&cli, beta)
+            }
+            (None, None) => false,
         }
+    };
+    if !installed {
+        std::process::exit(UPDATE_AVAILABLE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that the command line definition is consistent.
+    #[test]
+    fn cli_debug_assert() {
+        Cli::command().debug_assert();
+    }
+
+    /// Test that the flags and the subcommands are parsed.
+    #[test]
+    fn cli_parse() {
+        let cli =
+            Cli::try_parse_from(["geforcedrvchk3", "--check-only", "--channel", "studio"]).unwrap();
+        assert!(cli.check_only);
+        assert_eq!(cli.channel, Some(DriverChannel::Studio));
+        assert_eq!(cli.download_dir(), Path::new("."));
+        assert!(Cli::try_parse_from(["geforcedrvchk3", "--download", "--check-only"]).is_err());
+        assert!(Cli::try_parse_from(["geforcedrvchk3", "--offline", "--download"]).is_err());
+        let offline = |install: &str| {
+            Cli::try_parse_from(["geforcedrvchk3", "--offline", install])
+                .and_then(Cli::check_conflicts)
+        };
+        assert!(offline("--install").is_err());
+        assert!(offline("--install=false").is_ok());
+        let cli =
+            Cli::try_parse_from(["geforcedrvchk3", "--mirror", "\\\\share\\drivers"]).unwrap();
+        assert_eq!(cli.mirror.as_deref(), Some("\\\\share\\drivers"));
+        assert_eq!(cli.output_format(), OutputFormat::Text);
+        let cli = Cli::try_parse_from(["geforcedrvchk3", "--json"]).unwrap();
+        assert_eq!(cli.output_format(), OutputFormat::Json);
+        let cli = Cli::try_parse_from(["geforcedrvchk3", "--format", "csv"]).unwrap();
+        assert_eq!(cli.output_format(), OutputFormat::Csv);
+        let cli = Cli::try_parse_from(["geforcedrvchk3", "verify", "566.14-win11.exe"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Verify { sha256: None, .. })
+        ));
+        let cli =
+            Cli::try_parse_from(["geforcedrvchk3", "mirror", "mirror", "--keep", "3"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Mirror { keep: 3, max_age: None, ref queries, .. }) if queries.is_empty()
+        ));
+        assert!(
+            Cli::try_parse_from(["geforcedrvchk3", "mirror", "mirror", "--keep", "0"]).is_err()
+        );
+        let cli = Cli::try_parse_from(["geforcedrvchk3", "notes", "566.14"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Notes { version: Some(version) }) if version == DriverVersion::new(566, 14)
+        ));
+        assert!(Cli::try_parse_from(["geforcedrvchk3", "notes", "latest"]).is_err());
+    }
+
+    /// Test that the flags given on the command line or in the environment
+    /// variables override the configuration file, also when they are false.
+    #[test]
+    fn cli_flags_override_config() {
+        let settings = Config::parse(
+            "beta = true\ninstall = true\n[notifications]\nquiet = true\npause = false\n",
+            Path::new("config.toml"),
+        )
+        .unwrap();
+        let cli = Cli::try_parse_from([
+            "geforcedrvchk3",
+            "--beta=false",
+            "--install=0",
+            "--quiet=false",
+            "--no-pause=false",
+        ])
+        .unwrap()
+        .with_settings(settings.clone());
+        assert!(!cli.beta() && !cli.install() && !cli.quiet() && !cli.no_pause());
+
+        let cli = Cli::try_parse_from(["geforcedrvchk3"])
+            .unwrap()
+            .with_settings(settings.clone());
+        assert!(cli.beta() && cli.install() && cli.quiet() && cli.no_pause());
+
+        std::env::set_var("GEFORCEDRVCHK3_BETA", "0");
+        let cli = Cli::try_parse_from(["geforcedrvchk3"]).unwrap();
+        std::env::remove_var("GEFORCEDRVCHK3_BETA");
+        assert!(!cli.with_settings(settings).beta());
+    }
+
+    /// Test that the mirror queries select the product and the channel.
+    #[test]
+    fn mirror_query_success() {
+        let cli = Cli::try_parse_from(["geforcedrvchk3", "--beta"]).unwrap();
+        let base = DriverQuery::new();
+        let query = mirror_query(&cli, &base, "GeForce RTX 4070:studio").unwrap();
+        let product = Catalog::bundled().find_product("GeForce RTX 4070").unwrap();
+        assert_eq!(
+            query,
+            base.clone()
+                .product(&product)
+                .channel(DriverChannel::Studio)
+                .beta(true)
+        );
+        assert_eq!(
+            mirror_query(&cli, &base, "").unwrap(),
+            base.clone().beta(true)
+        );
+        assert!(matches!(
+            mirror_query(&cli, &base, "GeForce RTX 4070:weekly"),
+            Err(DriverCheckError::UnknownChannel(_))
+        ));
+        assert!(matches!(
+            mirror_query(&cli, &base, "GeForce 256"),
+            Err(DriverCheckError::UnknownProduct(_))
+        ));
+    }
+
+    /// Test that the version is found from the installer file names.
+    #[test]
+    fn installer_version_success() {
+        assert_eq!(
+            installer_version(Path::new(
+                "566.14-desktop-win10-win11-64bit-international-dch-whql.exe"
+            )),
+            Some(DriverVersion::new(566, 14))
+        );
+        assert_eq!(
+            installer_version(Path::new("NVIDIA-Linux-x86_64-550.54.14.run"))
+                .map(|version| version.to_string()),
+            Some("550.54.14".to_string())
+        );
+        assert_eq!(installer_version(Path::new("setup.exe")), None);
     }
 }
@@ -0,0 +1,900 @@
+//! Driver releases of a local mirror, e.g. an intranet share of an
+//! air-gapped network.
+//!
+//! The mirror is described by a JSON manifest listing the releases:
+//!
+//! ```json
+//! {
+//!   "releases": [
+//!     {
+//!       "version": "566.14",
+//!       "channel": "Game Ready",
+//!       "product_family": 1022,
+//!       "os": 57,
+//!       "release_date": "2024-11-19",
+//!       "url": "566.14/566.14-desktop-win10-win11-64bit-international-dch-whql.exe",
+//!       "size": 710064210,
+//!       "sha256": "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! The relative installer URLs are relative to the manifest. The mirror can
+//! be populated with `MirrorSync`, which downloads the releases found with
+//! the driver queries and writes the manifest.
+
+use crate::{
+    download_file, download_file_name, list_available_drivers, verify_installer, DownloadProgress,
+    DriverChannel, DriverCheckError, DriverQuery, DriverRelease, DriverSource, DriverVersion,
+    ExpectedSize, HttpFetcher, ReleaseDate,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the manifest in the mirror directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Index of the releases of a mirror.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorManifest {
+    pub releases: Vec<MirrorRelease>,
+}
+
+/// A release in the mirror manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorRelease {
+    pub version: DriverVersion,
+    /// Channel of the release. A release without a channel matches every
+    /// channel.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<DriverChannel>,
+    /// Product family ID ("pfid") of the lookup that found the release. A
+    /// release without a product matches every product.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product_family: Option<u32>,
+    /// Operating system ID ("osID") of the lookup that found the release. A
+    /// release without an operating system matches every operating system.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<u32>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default)]
+    pub beta: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<ReleaseDate>,
+    /// URL or path of the installer, relative to the manifest unless
+    /// absolute.
+    pub url: String,
+    /// Size of the installer in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// SHA-256 checksum of the installer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// URL the installer was originally downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+}
+
+impl MirrorRelease {
+    /// Tells whether the release was found with the same lookup, apart from
+    /// the version, as the other one.
+    fn same_lookup(&self, other: &MirrorRelease) -> bool {
+        self.channel == other.channel
+            && self.product_family == other.product_family
+            && self.os == other.os
+    }
+}
+
+impl MirrorManifest {
+    /// Parses the manifest JSON.
+    pub fn parse(text: &str) -> Result<MirrorManifest, DriverCheckError> {
+        serde_json::from_str(text).map_err(DriverCheckError::MalformedManifest)
+    }
+
+    /// Returns the manifest as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("manifest is always serializable")
+    }
+}
+
+/// Tells whether the location is an HTTP(S) URL rather than a path.
+fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Drivers from a local mirror given by the base URL of an HTTP share or by
+/// a path of a directory. The location can also point at the manifest
+/// itself.
+pub struct MirrorSource<F: HttpFetcher> {
+    pub fetcher: F,
+    pub location: String,
+    /// Only the releases of the channel are used, if set.
+    pub channel: Option<DriverChannel>,
+    /// Only the releases of the product family are used, if set.
+    pub product_family: Option<u32>,
+    /// Only the releases of the operating system are used, if set.
+    pub os: Option<u32>,
+}
+
+impl<F: HttpFetcher> MirrorSource<F> {
+    pub fn new(fetcher: F, location: &str) -> MirrorSource<F> {
+        MirrorSource {
+            fetcher,
+            location: location.to_string(),
+            channel: None,
+            product_family: None,
+            os: None,
+        }
+    }
+
+    /// Uses only the releases of the channel.
+    pub fn channel(mut self, channel: DriverChannel) -> MirrorSource<F> {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Uses only the releases of the product family.
+    pub fn product_family(mut self, pfid: u32) -> MirrorSource<F> {
+        self.product_family = Some(pfid);
+        self
+    }
+
+    /// Uses only the releases of the operating system.
+    pub fn os(mut self, os_id: u32) -> MirrorSource<F> {
+        self.os = Some(os_id);
+        self
+    }
+
+    /// Tells whether the release matches the channel, the product family
+    /// and the operating system of the source.
+    fn matches(&self, release: &MirrorRelease) -> bool {
+        fn matching<T: PartialEq>(wanted: Option<T>, value: Option<T>) -> bool {
+            wanted.is_none() || value.is_none() || wanted == value
+        }
+        matching(self.channel, release.channel)
+            && matching(self.product_family, release.product_family)
+            && matching(self.os, release.os)
+    }
+
+    /// Returns the URL or the path of the manifest.
+    pub fn manifest_location(&self) -> String {
+        if self.location.ends_with(".json") {
+            self.location.clone()
+        } else if is_url(&self.location) {
+            format!("{}/{MANIFEST_FILE}", self.location.trim_end_matches('/'))
+        } else {
+            Path::new(&self.location)
+                .join(MANIFEST_FILE)
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+
+    /// Reads the manifest with the fetcher or from the file system.
+    pub fn manifest(&self) -> Result<MirrorManifest, DriverCheckError> {
+        let location = self.manifest_location();
+        let text = if is_url(&location) {
+            self.fetcher.fetch(&location)?
+        } else {
+            fs::read_to_string(&location).map_err(|source| DriverCheckError::ManifestFile {
+                path: PathBuf::from(&location),
+                source,
+            })?
+        };
+        MirrorManifest::parse(&text)
+    }
+
+    /// Resolves the installer URL of the manifest relative to the manifest.
+    fn resolve(&self, manifest: &str, url: &str) -> String {
+        if is_url(url) || url.starts_with("file:") || Path::new(url).is_absolute() {
+            return url.to_string();
+        }
+        if is_url(manifest) {
+            return Url::parse(manifest)
+                .and_then(|base| base.join(url))
+                .map_or_else(|_| url.to_string(), String::from);
+        }
+        Path::new(manifest)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(url)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl<F: HttpFetcher> DriverSource for MirrorSource<F> {
+    fn latest(&self) -> Result<DriverRelease, DriverCheckError> {
+        self.releases()?
+            .into_iter()
+            .find(|release| !release.beta)
+            .ok_or(DriverCheckError::MissingField("version"))
+    }
+
+    fn releases(&self) -> Result<Vec<DriverRelease>, DriverCheckError> {
+        let location = self.manifest_location();
+        let mut releases: Vec<DriverRelease> = self
+            .manifest()?
+            .releases
+            .into_iter()
+            .filter(|release| self.matches(release))
+            .map(|release| DriverRelease {
+                name: release.name,
+                release_date: release.release_date,
+                file_size: release.size,
+                file_size_exact: true,
+                beta: release.beta,
+                sha256: release.sha256,
+                ..DriverRelease::new(release.version, &self.resolve(&location, &release.url))
+            })
+            .collect();
+        releases.sort_by_key(|release| Reverse(release.version));
+        Ok(releases)
+    }
+}
+
+/// How many mirrored releases are kept. The releases are counted per
+/// channel, product and operating system, and the stable and the beta
+/// releases separately, so that the beta releases never push the stable
+/// ones out of the mirror.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// Number of the newest releases kept per channel, product and operating
+    /// system.
+    pub keep: usize,
+    /// Releases older than this are pruned, except the newest stable and
+    /// the newest beta release of each channel, product and operating
+    /// system. The releases without a release date are not pruned by age.
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for Retention {
+    /// Keeps only the newest stable and beta release of each channel,
+    /// product and operating system.
+    fn default() -> Self {
+        Retention {
+            keep: 1,
+            max_age_days: None,
+        }
+    }
+}
+
+impl Retention {
+    /// Splits the releases into the kept and the pruned ones, both newest
+    /// first. `today` is the number of days since the Unix epoch.
+    fn apply(
+        &self,
+        mut releases: Vec<MirrorRelease>,
+        today: i64,
+    ) -> (Vec<MirrorRelease>, Vec<MirrorRelease>) {
+        releases.sort_by_key(|release| Reverse(release.version));
+        let mut kept: Vec<MirrorRelease> = Vec::new();
+        let mut pruned = Vec::new();
+        for release in releases {
+            let newer = kept
+                .iter()
+                .filter(|other| other.same_lookup(&release) && other.beta == release.beta)
+                .count();
+            let too_old = match (self.max_age_days, release.release_date) {
+                (Some(days), Some(date)) => today - days_since_epoch(date) > i64::from(days),
+                _ => false,
+            };
+            if newer == 0 || (newer < self.keep && !too_old) {
+                kept.push(release);
+            } else {
+                pruned.push(release);
+            }
+        }
+        (kept, pruned)
+    }
+}
+
+/// Returns the number of days from 1970-01-01 to the date.
+fn days_since_epoch(date: ReleaseDate) -> i64 {
+    let (month, day) = (i64::from(date.month), i64::from(date.day));
+    // The year is counted from March, so that the leap day is the last one.
+    let year = i64::from(date.year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the number of days from 1970-01-01 to today.
+fn today() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| (time.as_secs() / 86_400) as i64)
+        .unwrap_or_default()
+}
+
+/// Changes made by a mirror sync.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirrorUpdate {
+    /// The written manifest.
+    pub manifest: MirrorManifest,
+    /// The downloaded releases.
+    pub added: Vec<MirrorRelease>,
+    /// The releases removed by the retention policy.
+    pub pruned: Vec<MirrorRelease>,
+}
+
+/// Populates a mirror directory with the releases found with the driver
+/// queries.
+///
+/// The installers are downloaded to a subdirectory named by the version,
+/// e.g. "566.14/566.14-desktop-win10-win11-64bit-international-dch-whql.exe",
+/// and listed in the manifest with their size and SHA-256 checksum. The
+/// releases already in the manifest are not downloaded again.
+pub struct MirrorSync<F: HttpFetcher> {
+    pub fetcher: F,
+    pub directory: PathBuf,
+    pub queries: Vec<DriverQuery>,
+    pub retention: Retention,
+}
+
+impl<F: HttpFetcher> MirrorSync<F> {
+    pub fn new(fetcher: F, directory: impl Into<PathBuf>) -> MirrorSync<F> {
+        MirrorSync {
+            fetcher,
+            directory: directory.into(),
+            queries: Vec::new(),
+            retention: Retention::default(),
+        }
+    }
+
+    /// Adds a driver query. The releases are listed in the manifest with the
+    /// channel of the query.
+    pub fn query(mut self, query: DriverQuery) -> MirrorSync<F> {
+        self.queries.push(query);
+        self
+    }
+
+    /// Sets the retention policy.
+    pub fn retention(mut self, retention: Retention) -> MirrorSync<F> {
+        self.retention = retention;
+        self
+    }
+
+    /// Looks the releases up, downloads the new ones, prunes the old ones
+    /// and writes the manifest. The progress callback is called for every
+    /// downloaded chunk.
+    pub fn run(
+        &self,
+        mut progress: impl FnMut(&MirrorRelease, DownloadProgress),
+    ) -> Result<MirrorUpdate, DriverCheckError> {
+        let manifest_path = self.directory.join(MANIFEST_FILE);
+        let manifest_error = |source| DriverCheckError::ManifestFile {
+            path: manifest_path.clone(),
+            source,
+        };
+        fs::create_dir_all(&self.directory).map_err(|source| DriverCheckError::DownloadFile {
+            path: self.directory.clone(),
+            source,
+        })?;
+        let mut releases = match fs::read_to_string(&manifest_path) {
+            Ok(text) => MirrorManifest::parse(&text)?.releases,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(source) => return Err(manifest_error(source)),
+        };
+        for query in &self.queries {
+            for release in list_available_drivers(&self.fetcher, query)? {
+                let release = MirrorRelease {
+                    version: release.version,
+                    channel: Some(query.channel),
+                    product_family: Some(query.product_family),
+                    os: Some(query.os),
+                    name: release.name,
+                    beta: release.beta,
+                    release_date: release.release_date,
+                    url: String::new(),
+                    size: release.file_size,
+                    sha256: None,
+                    source_url: Some(release.download_url),
+                };
+                let mirrored = releases
+                    .iter()
+                    .any(|other| other.version == release.version && other.same_lookup(&release));
+                if !mirrored {
+                    releases.push(release);
+                }
+            }
+        }
+
+        let (mut releases, pruned) = self.retention.apply(releases, today());
+        let mut added = Vec::new();
+        for index in 0..releases.len() {
+            if !releases[index].url.is_empty() {
+                continue;
+            }
+            let mut release = releases[index].clone();
+            let downloaded = releases.iter().find(|other| {
+                !other.url.is_empty()
+                    && other.source_url.is_some()
+                    && other.source_url == release.source_url
+            });
+            match downloaded {
+                // The products of a series share the installer.
+                Some(other) => {
+                    release.url = other.url.clone();
+                    release.size = other.size;
+                    release.sha256 = other.sha256.clone();
+                }
+                None => self.download(&mut release, &releases, &mut progress)?,
+            }
+            releases[index] = release.clone();
+            added.push(release);
+        }
+        for release in &pruned {
+            self.remove(release, &releases)?;
+        }
+
+        let manifest = MirrorManifest { releases };
+        let part = self.directory.join(format!("{MANIFEST_FILE}.part"));
+        fs::write(&part, manifest.to_json())
+            .and_then(|_| fs::rename(&part, &manifest_path))
+            .map_err(manifest_error)?;
+        Ok(MirrorUpdate {
+            manifest,
+            added,
+            pruned,
+        })
+    }
+
+    /// Downloads the installer of a new release and fills in its mirror URL,
+    /// size and checksum. The size announced by the service is verified. An
+    /// installer having the same name as another one of the mirrored
+    /// releases is downloaded to a subdirectory named by the product family
+    /// and the operating system.
+    fn download(
+        &self,
+        release: &mut MirrorRelease,
+        mirrored: &[MirrorRelease],
+        progress: &mut impl FnMut(&MirrorRelease, DownloadProgress),
+    ) -> Result<(), DriverCheckError> {
+        let source_url = release.source_url.clone().unwrap_or_default();
+        let mut url = format!("{}/{}", release.version, download_file_name(&source_url));
+        if mirrored.iter().any(|other| other.url == url) {
+            url = format!(
+                "{}/{}-{}/{}",
+                release.version,
+                release.product_family.unwrap_or_default(),
+                release.os.unwrap_or_default(),
+                download_file_name(&source_url)
+            );
+        }
+        let directory = self
+            .directory
+            .join(Path::new(&url).parent().unwrap_or(Path::new("")));
+        fs::create_dir_all(&directory).map_err(|source| DriverCheckError::DownloadFile {
+            path: directory.clone(),
+            source,
+        })?;
+        let path = download_file(&source_url, &directory, |downloaded| {
+            progress(release, downloaded)
+        })?;
+        release.sha256 = Some(verify_installer(
+            &path,
+            release.size.map(ExpectedSize::Announced),
+            None,
+        )?);
+        release.size = fs::metadata(&path).map(|meta| meta.len()).ok();
+        release.url = url;
+        Ok(())
+    }
+
+    /// Returns the path of an installer in the mirror directory. The URLs
+    /// of the hand-written manifests can point anywhere, so only the
+    /// relative paths staying inside the directory are returned.
+    fn installer_path(&self, url: &str) -> Option<PathBuf> {
+        if url.is_empty() || is_url(url) || url.starts_with("file:") {
+            return None;
+        }
+        let relative = Path::new(url);
+        let inside = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        inside.then(|| self.directory.join(relative))
+    }
+
+    /// Removes the installer of a pruned release, unless a kept release
+    /// shares it, and the directories left empty. The installers outside
+    /// the mirror directory are not removed.
+    fn remove(
+        &self,
+        release: &MirrorRelease,
+        kept: &[MirrorRelease],
+    ) -> Result<(), DriverCheckError> {
+        if kept.iter().any(|other| other.url == release.url) {
+            return Ok(());
+        }
+        let Some(path) = self.installer_path(&release.url) else {
+            return Ok(());
+        };
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => return Err(DriverCheckError::DownloadFile { path, source }),
+        }
+        let mut directory = path.parent();
+        while let Some(dir) = directory.filter(|dir| *dir != self.directory) {
+            // Fails if other installers are left in the directory.
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+            directory = dir.parent();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_page;
+    use crate::test_server::{Response, TestServer};
+    use std::sync::{Arc, Mutex};
+
+    const MANIFEST: &str = include_str!("../fixtures/mirror_manifest.json");
+
+    /// Test that the manifest is parsed.
+    #[test]
+    fn mirror_manifest_parse() {
+        let manifest = MirrorManifest::parse(MANIFEST).unwrap();
+        assert_eq!(manifest.releases.len(), 4);
+        let release = &manifest.releases[0];
+        assert_eq!(release.version, DriverVersion::new(566, 14));
+        assert_eq!(release.channel, Some(DriverChannel::GameReady));
+        assert_eq!(release.release_date.unwrap().to_string(), "2024-11-19");
+        assert_eq!(release.size, Some(710_064_210));
+        assert_eq!(
+            MirrorManifest::parse(&manifest.to_json()).unwrap(),
+            manifest
+        );
+        assert!(matches!(
+            MirrorManifest::parse("{ \"releases\": [ { \"version\": \"x\" } ] }"),
+            Err(DriverCheckError::MalformedManifest(_))
+        ));
+    }
+
+    /// Test that the releases are read from an HTTP share and the relative
+    /// URLs are resolved against the manifest URL.
+    #[test]
+    fn mirror_source_url() {
+        let server = TestServer::serve(&[("/drivers/manifest.json", MANIFEST)]);
+        let source = MirrorSource::new(get_page, &format!("{}drivers", server.url))
+            .channel(DriverChannel::GameReady);
+        let latest = source.latest().unwrap();
+        assert_eq!(latest.version, DriverVersion::new(566, 14));
+        assert_eq!(
+            latest.download_url,
+            format!("{}drivers/566.14/566.14-win11.exe", server.url)
+        );
+        assert_eq!(latest.sha256.as_deref().map(str::len), Some(64));
+
+        let releases = source.releases().unwrap();
+        let versions: Vec<String> = releases.iter().map(|r| r.version.to_string()).collect();
+        assert_eq!(versions, ["566.36", "566.14", "565.90"]);
+        assert!(releases[0].beta);
+        assert_eq!(
+            releases[2].download_url,
+            "https://us.download.nvidia.com/Windows/565.90/565.90-win11.exe"
+        );
+    }
+
+    /// Test that the releases are read from a directory.
+    #[test]
+    fn mirror_source_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(MANIFEST_FILE), MANIFEST).unwrap();
+        let source = MirrorSource::new(get_page, dir.path().to_str().unwrap())
+            .channel(DriverChannel::Studio);
+        let latest = source.latest().unwrap();
+        assert_eq!(latest.version, DriverVersion::new(566, 14));
+        let releases = source.releases().unwrap();
+        assert_eq!(releases.len(), 2);
+        assert_eq!(
+            Path::new(&releases[0].download_url),
+            dir.path().join("566.14/566.14-win11-studio.exe")
+        );
+
+        let source = MirrorSource::new(get_page, dir.path().join("missing").to_str().unwrap());
+        assert!(matches!(
+            source.latest(),
+            Err(DriverCheckError::ManifestFile { .. })
+        ));
+    }
+
+    /// Test that the releases of other products and operating systems are
+    /// filtered out.
+    #[test]
+    fn mirror_source_product_and_os() {
+        let manifest = r#"{ "releases": [
+            { "version": "566.14", "product_family": 1022, "os": 57, "url": "566.14/desktop.exe" },
+            { "version": "566.14", "product_family": 1023, "os": 57, "url": "566.14/notebook.exe" },
+            { "version": "565.90", "os": 135, "url": "565.90/win11.exe" },
+            { "version": "565.77", "url": "565.77/any.exe" }
+        ] }"#;
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(MANIFEST_FILE), manifest).unwrap();
+        let names = |source: &dyn DriverSource| {
+            source
+                .releases()
+                .unwrap()
+                .iter()
+                .map(|release| download_file_name(&release.download_url))
+                .collect::<Vec<String>>()
+        };
+        let source = || MirrorSource::new(get_page, dir.path().to_str().unwrap());
+
+        assert_eq!(
+            names(&source().product_family(1023).os(57)),
+            ["notebook.exe", "any.exe"]
+        );
+        assert_eq!(
+            names(&source().product_family(1022).os(135)),
+            ["win11.exe", "any.exe"]
+        );
+        assert_eq!(names(&source()).len(), 4);
+    }
+
+    /// Returns a release of the retention tests.
+    fn release(version: &str, channel: DriverChannel, date: &str) -> MirrorRelease {
+        MirrorRelease {
+            version: version.parse().unwrap(),
+            channel: Some(channel),
+            product_family: None,
+            os: None,
+            name: String::new(),
+            beta: false,
+            release_date: Some(date.parse().unwrap()),
+            url: format!("{version}/{version}.exe"),
+            size: None,
+            sha256: None,
+            source_url: None,
+        }
+    }
+
+    /// Test that the newest releases of each channel are kept.
+    #[test]
+    fn retention_apply() {
+        let releases = vec![
+            release("565.90", DriverChannel::GameReady, "2024-10-22"),
+            release("566.14", DriverChannel::GameReady, "2024-11-19"),
+            release("561.09", DriverChannel::GameReady, "2024-09-10"),
+            release("561.09", DriverChannel::Studio, "2024-09-10"),
+        ];
+        let today = days_since_epoch("2024-12-31".parse().unwrap());
+        let versions = |releases: &[MirrorRelease]| -> Vec<String> {
+            releases.iter().map(|r| r.version.to_string()).collect()
+        };
+
+        let retention = Retention {
+            keep: 2,
+            max_age_days: None,
+        };
+        let (kept, pruned) = retention.apply(releases.clone(), today);
+        assert_eq!(versions(&kept), ["566.14", "565.90", "561.09"]);
+        assert_eq!(versions(&pruned), ["561.09"]);
+        assert_eq!(pruned[0].channel, Some(DriverChannel::GameReady));
+
+        // The newest release of a channel is kept even if it is too old.
+        let retention = Retention {
+            keep: 10,
+            max_age_days: Some(90),
+        };
+        let (kept, pruned) = retention.apply(releases.clone(), today);
+        assert_eq!(versions(&kept), ["566.14", "565.90", "561.09"]);
+        assert_eq!(kept[2].channel, Some(DriverChannel::Studio));
+        assert_eq!(versions(&pruned), ["561.09"]);
+
+        // A newer beta release does not prune the only stable release.
+        let mut beta = release("566.36", DriverChannel::GameReady, "2024-12-05");
+        beta.beta = true;
+        let mut releases = releases;
+        releases.push(beta);
+        let (kept, pruned) = Retention::default().apply(releases, today);
+        assert_eq!(versions(&kept), ["566.36", "566.14", "561.09"]);
+        assert!(kept[0].beta && !kept[1].beta);
+        assert_eq!(versions(&pruned), ["565.90", "561.09"]);
+
+        // The releases of another product do not prune the ones of the
+        // product.
+        let mut other = release("566.36", DriverChannel::Studio, "2024-12-05");
+        other.product_family = Some(1023);
+        let releases = vec![
+            release("566.14", DriverChannel::Studio, "2024-11-19"),
+            other,
+        ];
+        let (kept, pruned) = Retention::default().apply(releases, today);
+        assert_eq!(versions(&kept), ["566.36", "566.14"]);
+        assert!(pruned.is_empty());
+    }
+
+    /// Test that only the installers inside the mirror directory are
+    /// removed.
+    #[test]
+    fn mirror_sync_remove_outside() {
+        let dir = tempfile::tempdir().unwrap();
+        let mirror = dir.path().join("mirror");
+        fs::create_dir_all(mirror.join("566.14")).unwrap();
+        let outside = dir.path().join("outside.exe");
+        fs::write(&outside, "keep").unwrap();
+        fs::write(mirror.join("566.14/566.14.exe"), "installer").unwrap();
+        let sync = MirrorSync::new(get_page, &mirror);
+
+        let mut pruned = release("565.90", DriverChannel::GameReady, "2024-10-22");
+        for url in [
+            outside.to_str().unwrap(),
+            "../outside.exe",
+            "566.14/../../outside.exe",
+            "https://us.download.nvidia.com/Windows/565.90/565.90-win11.exe",
+        ] {
+            pruned.url = url.to_string();
+            sync.remove(&pruned, &[]).unwrap();
+            assert!(outside.exists(), "{url}");
+        }
+
+        pruned.url = "566.14/566.14.exe".to_string();
+        sync.remove(&pruned, &[]).unwrap();
+        assert!(!mirror.join("566.14").exists());
+        assert!(mirror.exists());
+    }
+
+    /// Test that the days are counted from the Unix epoch.
+    #[test]
+    fn days_since_epoch_success() {
+        assert_eq!(days_since_epoch("1970-01-01".parse().unwrap()), 0);
+        assert_eq!(days_since_epoch("2000-03-01".parse().unwrap()), 11_017);
+        assert_eq!(days_since_epoch("2024-11-19".parse().unwrap()), 20_046);
+    }
+
+    /// Returns an AjaxDriverService response listing the versions with their
+    /// installers on the server.
+    fn lookup_response(server_url: &str, versions: &[&str]) -> String {
+        let entries: Vec<String> = versions
+            .iter()
+            .map(|version| {
+                format!(
+                    r#"{{ "downloadInfo": {{ "Version": "{version}", "Name": "GeForce%20Game%20Ready%20Driver", "ReleaseDateTime": "Tue Nov 19, 2024", "DownloadURL": "{server_url}drivers/{version}-win11.exe" }} }}"#
+                )
+            })
+            .collect();
+        format!(r#"{{ "IDS": [ {} ] }}"#, entries.join(", "))
+    }
+
+    /// Test that the same version of several products and operating systems
+    /// is mirrored once per lookup, and that the installers are shared or
+    /// kept apart by their URLs.
+    #[test]
+    fn mirror_sync_products() {
+        let server = TestServer::start(|request| match request.path.strip_prefix("/drivers/") {
+            Some(name) => Response::ok(format!("installer {name}")),
+            None => Response::status(404),
+        });
+        let fetcher = {
+            let server_url = server.url.clone();
+            move |url: &str| {
+                let path = if url.contains("osID=135") {
+                    "win11"
+                } else {
+                    "win10"
+                };
+                Ok(format!(
+                    r#"{{ "IDS": [ {{ "downloadInfo": {{ "Version": "566.14", "DownloadURL": "{server_url}drivers/{path}/566.14.exe" }} }} ] }}"#
+                ))
+            }
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let update = MirrorSync::new(fetcher, dir.path())
+            .query(DriverQuery::new().product_family(1022))
+            .query(DriverQuery::new().product_family(1023))
+            .query(DriverQuery::new().product_family(1022).os(135))
+            .run(|_, _| {})
+            .unwrap();
+
+        let releases = &update.manifest.releases;
+        let lookups: Vec<(Option<u32>, Option<u32>, &str)> = releases
+            .iter()
+            .map(|release| (release.product_family, release.os, release.url.as_str()))
+            .collect();
+        assert_eq!(
+            lookups,
+            [
+                (Some(1022), Some(57), "566.14/566.14.exe"),
+                (Some(1023), Some(57), "566.14/566.14.exe"),
+                (Some(1022), Some(135), "566.14/1022-135/566.14.exe"),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("566.14/1022-135/566.14.exe")).unwrap(),
+            "installer win11/566.14.exe"
+        );
+        assert_eq!(releases[0].sha256, releases[1].sha256);
+        assert_ne!(releases[0].sha256, releases[2].sha256);
+        let downloads = server
+            .requests()
+            .iter()
+            .filter(|request| request.path.starts_with("/drivers/"))
+            .count();
+        assert_eq!(downloads, 2);
+    }
+
+    /// Test that the releases are mirrored, kept up to date and pruned, and
+    /// that the mirror can be used as a source.
+    #[test]
+    fn mirror_sync_end_to_end() {
+        let server = TestServer::start(|request| match request.path.strip_prefix("/drivers/") {
+            Some(name) => Response::ok(format!("installer {name}")),
+            None => Response::status(404),
+        });
+        let announced = Arc::new(Mutex::new(vec!["566.14", "565.90", "561.09"]));
+        let fetcher = {
+            let announced = Arc::clone(&announced);
+            let server_url = server.url.clone();
+            move |_: &str| Ok(lookup_response(&server_url, &announced.lock().unwrap()))
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let sync = MirrorSync::new(fetcher, dir.path())
+            .query(DriverQuery::new())
+            .retention(Retention {
+                keep: 2,
+                max_age_days: None,
+            });
+
+        let update = sync.run(|_, _| {}).unwrap();
+        assert_eq!(update.added.len(), 2);
+        assert_eq!(update.pruned.len(), 1);
+        let release = &update.manifest.releases[0];
+        assert_eq!(release.version, DriverVersion::new(566, 14));
+        assert_eq!(release.channel, Some(DriverChannel::GameReady));
+        assert_eq!(release.url, "566.14/566.14-win11.exe");
+        assert_eq!(
+            release.source_url,
+            Some(format!("{}drivers/566.14-win11.exe", server.url))
+        );
+        let installer = dir.path().join("566.14/566.14-win11.exe");
+        assert_eq!(release.size, Some(fs::metadata(&installer).unwrap().len()));
+        assert_eq!(
+            release.sha256.as_deref(),
+            Some(crate::sha256_file(&installer).unwrap().as_str())
+        );
+        assert!(!dir.path().join("561.09").exists());
+
+        announced.lock().unwrap().insert(0, "566.36");
+        let update = sync.run(|_, _| {}).unwrap();
+        let versions: Vec<String> = update
+            .manifest
+            .releases
+            .iter()
+            .map(|r| r.version.to_string())
+            .collect();
+        assert_eq!(versions, ["566.36", "566.14"]);
+        assert_eq!(update.added.len(), 1);
+        assert!(!dir.path().join("565.90").exists());
+        let downloads = server
+            .requests()
+            .iter()
+            .filter(|request| request.path == "/drivers/566.14-win11.exe")
+            .count();
+        assert_eq!(downloads, 1);
+
+        let written = fs::read_to_string(dir.path().join(MANIFEST_FILE)).unwrap();
+        assert_eq!(MirrorManifest::parse(&written).unwrap(), update.manifest);
+        let source = MirrorSource::new(get_page, dir.path().to_str().unwrap());
+        let latest = source.latest().unwrap();
+        assert_eq!(latest.version, DriverVersion::new(566, 36));
+        assert_eq!(
+            fs::read_to_string(&latest.download_url).unwrap(),
+            "installer 566.36-win11.exe"
+        );
+    }
+}
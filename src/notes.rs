@@ -0,0 +1,178 @@
+//! Release notes of the driver releases as plain text.
+//!
+//! The service describes the changes of a release with HTML fragments, which
+//! are rendered as plain text for the terminal.
+
+use crate::{DriverRelease, DriverVersion, ReleaseDate};
+use regex::Regex;
+use std::fmt;
+
+/// Renders an HTML fragment as plain text. The tags are removed, the
+/// paragraphs are separated with blank lines, the list items are prefixed
+/// with "- " and the HTML entities are decoded.
+pub fn html_to_text(html: &str) -> String {
+    let comments = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    let html = comments.replace_all(html, "");
+    let tags = Regex::new(r"(?s)<(/?)([A-Za-z][A-Za-z0-9]*)[^>]*>").unwrap();
+    let whitespace = Regex::new(r"\s+").unwrap();
+
+    let mut text = String::new();
+    let push_text = |text: &mut String, fragment: &str| {
+        let fragment = whitespace.replace_all(fragment, " ");
+        text.push_str(&html_escape::decode_html_entities(&fragment));
+    };
+    let mut end = 0;
+    for tag in tags.captures_iter(&html) {
+        let whole = tag.get(0).unwrap();
+        push_text(&mut text, &html[end..whole.start()]);
+        end = whole.end();
+        let closing = !tag[1].is_empty();
+        match tag[2].to_ascii_lowercase().as_str() {
+            "br" => text.push('\n'),
+            "li" if !closing => text.push_str("\n- "),
+            "p" | "div" | "ul" | "ol" | "table" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5"
+            | "h6" => text.push_str("\n\n"),
+            _ => {}
+        }
+    }
+    push_text(&mut text, &html[end..]);
+
+    // Trim the lines and leave at most one blank line between paragraphs.
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// Release notes of a driver release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseNotes {
+    pub version: DriverVersion,
+    /// Name of the release, e.g. "GeForce Game Ready Driver".
+    pub name: String,
+    pub release_date: Option<ReleaseDate>,
+    /// Summary of the changes as plain text.
+    pub summary: Option<String>,
+    /// Additional notes as plain text.
+    pub other_notes: Option<String>,
+    /// URL of the full release notes, usually a PDF document.
+    pub release_notes_url: Option<String>,
+    /// URL of the driver details page.
+    pub details_url: Option<String>,
+}
+
+impl From<&DriverRelease> for ReleaseNotes {
+    fn from(release: &DriverRelease) -> ReleaseNotes {
+        let text = |html: &Option<String>| {
+            html.as_deref()
+                .map(html_to_text)
+                .filter(|text| !text.is_empty())
+        };
+        ReleaseNotes {
+            version: release.version,
+            name: release.name.clone(),
+            release_date: release.release_date,
+            summary: text(&release.brief_description),
+            other_notes: text(&release.other_notes),
+            release_notes_url: release.release_notes_url.clone(),
+            details_url: release.details_url.clone(),
+        }
+    }
+}
+
+/// Displays the notes for the terminal, starting with the name, the version
+/// and the release date of the release.
+impl fmt::Display for ReleaseNotes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name.as_str() {
+            "" => write!(f, "Driver {}", self.version)?,
+            name => write!(f, "{name} {}", self.version)?,
+        }
+        match self.release_date {
+            Some(date) => writeln!(f, " ({date})")?,
+            None => writeln!(f)?,
+        }
+        match (&self.other_notes, &self.summary) {
+            (None, None) => write!(f, "\nNo summary of the changes is available.\n")?,
+            (other_notes, summary) => {
+                for text in [other_notes, summary].into_iter().flatten() {
+                    write!(f, "\n{text}\n")?;
+                }
+            }
+        }
+        if self.release_notes_url.is_some() || self.details_url.is_some() {
+            writeln!(f)?;
+        }
+        if let Some(url) = &self.release_notes_url {
+            writeln!(f, "Release notes: {url}")?;
+        }
+        if let Some(url) = &self.details_url {
+            writeln!(f, "Details:       {url}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_driver_releases;
+
+    /// Test that the paragraphs and the lists are rendered as text.
+    #[test]
+    fn html_to_text_success() {
+        assert_eq!(
+            html_to_text(
+                "<p>Game Ready for <b>Indiana&nbsp;Jones</b>.</p>\n<ul>\n  <li>Fixed stutter</li>\
+                 <LI>Improved &quot;stability&quot; &amp; more</LI></ul><!-- <p>hidden</p> -->\
+                 First line<br/>Second line &lt;br&gt;"
+            ),
+            "Game Ready for Indiana\u{a0}Jones.\n\n\
+             - Fixed stutter\n\
+             - Improved \"stability\" & more\n\n\
+             First line\n\
+             Second line <br>"
+        );
+        assert_eq!(html_to_text("plain text"), "plain text");
+        assert_eq!(html_to_text("<p></p>"), "");
+    }
+
+    /// Test that the notes of a release are rendered from its HTML.
+    #[test]
+    fn release_notes_display() {
+        let releases =
+            parse_driver_releases(include_str!("../fixtures/ajax_driver_service.json")).unwrap();
+        let notes = ReleaseNotes::from(&releases[0]);
+        assert_eq!(
+            notes.other_notes.as_deref(),
+            Some("Game Ready for Microsoft Flight Simulator 2024")
+        );
+        let text = notes.to_string();
+        assert!(
+            text.starts_with("GeForce Game Ready Driver 566.14 (2024-11-19)\n\n"),
+            "{text}"
+        );
+        assert!(text.contains(
+            "\"S.T.A.L.K.E.R. 2: Heart of Chornobyl\".\n\n\
+             In addition, this driver supports the launch of Microsoft Flight Simulator 2024 & more.\n\n\
+             - Fixed stutter in some games\n\
+             - Improved stability\n"
+        ), "{text}");
+        assert!(text.ends_with(
+            "Release notes: https://us.download.nvidia.com/Windows/566.14/566.14-win11-win10-release-notes.pdf\n\
+             Details:       https://www.nvidia.com/en-us/drivers/details/232817/\n"
+        ), "{text}");
+
+        let notes = ReleaseNotes::from(&DriverRelease::new(DriverVersion::new(566, 14), ""));
+        assert_eq!(
+            notes.to_string(),
+            "Driver 566.14\n\nNo summary of the changes is available.\n"
+        );
+    }
+}
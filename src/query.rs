@@ -0,0 +1,223 @@
+//! Parameters of the NVIDIA driver lookup service.
+
+use crate::{DriverCheckError, Product};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+const NVIDIA_SERVICE_URL: &str = r"https://gfwsl.geforce.com/services_toolkit/services/com/nvidia/services/AjaxDriverService.php";
+
+/// Driver release channel.
+///
+/// GeForce cards have Game Ready and Studio drivers. Enterprise cards (NVIDIA
+/// RTX, Quadro etc.) have Production Branch and New Feature Branch drivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DriverChannel {
+    #[default]
+    GameReady,
+    Studio,
+    ProductionBranch,
+    NewFeatureBranch,
+}
+
+impl DriverChannel {
+    /// Channels available for GeForce cards.
+    pub const GEFORCE: [DriverChannel; 2] = [DriverChannel::GameReady, DriverChannel::Studio];
+
+    /// Channels available for enterprise cards.
+    pub const ENTERPRISE: [DriverChannel; 2] = [
+        DriverChannel::ProductionBranch,
+        DriverChannel::NewFeatureBranch,
+    ];
+
+    /// Returns the channels available for the card with the given name. The
+    /// GeForce and TITAN cards are consumer cards, all others are enterprise
+    /// cards.
+    pub fn for_gpu_name(name: &str) -> [DriverChannel; 2] {
+        let name = name.to_lowercase();
+        if name.contains("geforce") || name.contains("titan") {
+            DriverChannel::GEFORCE
+        } else {
+            DriverChannel::ENTERPRISE
+        }
+    }
+}
+
+impl fmt::Display for DriverChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DriverChannel::GameReady => "Game Ready",
+            DriverChannel::Studio => "Studio",
+            DriverChannel::ProductionBranch => "Production Branch",
+            DriverChannel::NewFeatureBranch => "New Feature Branch",
+        })
+    }
+}
+
+/// Serialized in the displayed form, e.g. "Game Ready".
+impl Serialize for DriverChannel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserialized with the same names as parsed from the command line, e.g.
+/// "studio" or "production-branch".
+impl<'de> Deserialize<'de> for DriverChannel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl FromStr for DriverChannel {
+    type Err = DriverCheckError;
+
+    /// Parses the channel name, ignoring case, spaces, dashes and
+    /// underscores, e.g. "Game Ready", "game-ready" or "studio".
+    fn from_str(s: &str) -> Result<DriverChannel, DriverCheckError> {
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .collect::<String>()
+            .to_lowercase();
+        match name.as_str() {
+            "gameready" | "grd" => Ok(DriverChannel::GameReady),
+            "studio" | "crd" => Ok(DriverChannel::Studio),
+            "productionbranch" | "production" | "pb" => Ok(DriverChannel::ProductionBranch),
+            "newfeaturebranch" | "newfeature" | "nfb" => Ok(DriverChannel::NewFeatureBranch),
+            _ => Err(DriverCheckError::UnknownChannel(s.to_string())),
+        }
+    }
+}
+
+/// Driver lookup query for the NVIDIA AjaxDriverService.
+///
+/// The default query asks for the GeForce GTX 1070 Ti DCH driver for 64-bit
+/// Windows 10 in US English. Each parameter can be changed with the builder
+/// methods:
+///
+/// ```
+/// use geforcedrvchk3::DriverQuery;
+///
+/// let query = DriverQuery::new().product_series(127).product_family(1022);
+/// assert!(query.url().contains("psid=127&pfid=1022"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverQuery {
+    pub product_series: u32,
+    pub product_family: u32,
+    pub os: u32,
+    pub language: u32,
+    pub dch: bool,
+    pub beta: bool,
+    pub whql: bool,
+    pub channel: DriverChannel,
+    pub number_of_results: u32,
+}
+
+impl Default for DriverQuery {
+    fn default() -> DriverQuery {
+        DriverQuery {
+            product_series: 101,
+            product_family: 859,
+            os: 57,
+            language: 1033,
+            dch: true,
+            beta: false,
+            whql: false,
+            channel: DriverChannel::GameReady,
+            number_of_results: 10,
+        }
+    }
+}
+
+impl DriverQuery {
+    /// Creates a query with the default parameters.
+    pub fn new() -> DriverQuery {
+        DriverQuery::default()
+    }
+
+    /// Sets the product series ID ("psid"), e.g. 101 for GeForce 10 Series.
+    pub fn product_series(mut self, psid: u32) -> DriverQuery {
+        self.product_series = psid;
+        self
+    }
+
+    /// Sets the product family ID ("pfid"), e.g. 859 for GeForce GTX 1070 Ti.
+    pub fn product_family(mut self, pfid: u32) -> DriverQuery {
+        self.product_family = pfid;
+        self
+    }
+
+    /// Sets the product series and family IDs of a product resolved from
+    /// the catalog.
+    pub fn product(self, product: &Product) -> DriverQuery {
+        self.product_series(product.series)
+            .product_family(product.family)
+    }
+
+    /// Sets the operating system ID ("osID"), e.g. 57 for Windows 10 64-bit.
+    pub fn os(mut self, os_id: u32) -> DriverQuery {
+        self.os = os_id;
+        self
+    }
+
+    /// Sets the language code ("languageCode"), e.g. 1033 for US English.
+    pub fn language(mut self, language_code: u32) -> DriverQuery {
+        self.language = language_code;
+        self
+    }
+
+    /// Selects between DCH and standard drivers.
+    pub fn dch(mut self, dch: bool) -> DriverQuery {
+        self.dch = dch;
+        self
+    }
+
+    /// Includes beta drivers in the results.
+    pub fn beta(mut self, beta: bool) -> DriverQuery {
+        self.beta = beta;
+        self
+    }
+
+    /// Limits the results to WHQL certified drivers.
+    pub fn whql(mut self, whql: bool) -> DriverQuery {
+        self.whql = whql;
+        self
+    }
+
+    /// Selects the driver release channel.
+    pub fn channel(mut self, channel: DriverChannel) -> DriverQuery {
+        self.channel = channel;
+        self
+    }
+
+    /// Sets the maximum number of drivers returned by the service.
+    pub fn number_of_results(mut self, count: u32) -> DriverQuery {
+        self.number_of_results = count;
+        self
+    }
+
+    /// Renders the query as an AjaxDriverService URL.
+    ///
+    /// Studio drivers are selected with "upCRD" (Creator Ready Driver) and
+    /// New Feature Branch drivers with "qnf" (Quadro New Feature).
+    pub fn url(&self) -> String {
+        let studio = self.channel == DriverChannel::Studio;
+        let new_feature = self.channel == DriverChannel::NewFeatureBranch;
+        format!(
+            "{NVIDIA_SERVICE_URL}?func=DriverManualLookup&psid={}&pfid={}&osID={}&languageCode={}&beta={}&isWHQL={}&dltype=-1&dch={}&upCRD={}&qnf={}&sort1=0&numberOfResults={}",
+            self.product_series,
+            self.product_family,
+            self.os,
+            self.language,
+            u8::from(self.beta),
+            u8::from(self.whql),
+            u8::from(self.dch),
+            u8::from(studio),
+            u8::from(new_feature),
+            self.number_of_results
+        )
+    }
+}
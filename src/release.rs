@@ -0,0 +1,501 @@
+//! Driver releases listed by the NVIDIA AjaxDriverService.
+
+use crate::{
+    DriverChannel, DriverCheckError, DriverQuery, DriverVersion, ExpectedSize, HttpFetcher,
+};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Release date of a driver. Displayed in "YYYY-MM-DD" form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReleaseDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl ReleaseDate {
+    /// Returns the number of days in the month, or 0 if the month is not
+    /// between 1 and 12.
+    fn days_in_month(year: u16, month: u8) -> u8 {
+        let leap =
+            year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+        match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => 0,
+        }
+    }
+}
+
+impl FromStr for ReleaseDate {
+    type Err = DriverCheckError;
+
+    /// Parses the "Tue Nov 19, 2024" form used by the AjaxDriverService, or
+    /// the "2024-11-19" form. The month and the day must exist in the
+    /// calendar.
+    fn from_str(s: &str) -> Result<ReleaseDate, DriverCheckError> {
+        let invalid = || DriverCheckError::ParseDate(s.to_string());
+        let trimmed = s.trim();
+        let (year, month, day) =
+            if let [year, month, day] = trimmed.split('-').collect::<Vec<&str>>()[..] {
+                (year, month.parse().map_err(|_| invalid())?, day)
+            } else {
+                let words: Vec<&str> = trimmed
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|word| !word.is_empty())
+                    .collect();
+                let [_, month, day, year] = words[..] else {
+                    return Err(invalid());
+                };
+                let month = MONTHS
+                    .iter()
+                    .position(|name| month.starts_with(name))
+                    .ok_or_else(invalid)?;
+                (year, month as u8 + 1, day)
+            };
+        let year: u16 = year.parse().map_err(|_| invalid())?;
+        let day: u8 = day.parse().map_err(|_| invalid())?;
+        if day == 0 || day > ReleaseDate::days_in_month(year, month) {
+            return Err(invalid());
+        }
+        Ok(ReleaseDate { year, month, day })
+    }
+}
+
+impl fmt::Display for ReleaseDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Serialized in the displayed form, e.g. "2024-11-19".
+impl Serialize for ReleaseDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserialized from the "YYYY-MM-DD" form.
+impl<'de> Deserialize<'de> for ReleaseDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let date = String::deserialize(deserializer)?;
+        date.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A driver release with its metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverRelease {
+    pub version: DriverVersion,
+    /// Name of the release, e.g. "GeForce Game Ready Driver".
+    pub name: String,
+    pub release_date: Option<ReleaseDate>,
+    pub download_url: String,
+    /// Size of the installation package in bytes, as announced by the
+    /// service. The service rounds the size, so it is only approximate
+    /// unless `file_size_exact` is set.
+    pub file_size: Option<u64>,
+    /// Whether the file size is exact, e.g. from a mirror manifest.
+    pub file_size_exact: bool,
+    pub whql: bool,
+    pub beta: bool,
+    pub recommended: bool,
+    pub release_notes_url: Option<String>,
+    pub details_url: Option<String>,
+    /// Additional notes as HTML.
+    pub other_notes: Option<String>,
+    /// Summary of the changes ("what's new") as HTML.
+    pub brief_description: Option<String>,
+    /// SHA-256 checksum of the installation package, if the source has it.
+    pub sha256: Option<String>,
+}
+
+impl DriverRelease {
+    /// Creates a release without any other metadata than the version and
+    /// the download URL.
+    pub fn new(version: DriverVersion, download_url: &str) -> DriverRelease {
+        DriverRelease {
+            version,
+            name: String::new(),
+            release_date: None,
+            download_url: download_url.to_string(),
+            file_size: None,
+            file_size_exact: false,
+            whql: false,
+            beta: false,
+            recommended: false,
+            release_notes_url: None,
+            details_url: None,
+            other_notes: None,
+            brief_description: None,
+            sha256: None,
+        }
+    }
+
+    /// Returns the size the downloaded installation package is verified
+    /// against, if the size is known.
+    pub fn expected_size(&self) -> Option<ExpectedSize> {
+        self.file_size.map(|size| match self.file_size_exact {
+            true => ExpectedSize::Exact(size),
+            false => ExpectedSize::Announced(size),
+        })
+    }
+}
+
+/// Decodes a percent-encoded field of the service, e.g.
+/// "GeForce%20Game%20Ready%20Driver".
+fn decode_field(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().into_owned()
+}
+
+/// Parses a file size, e.g. "677.17 MB", to bytes. The units are powers of
+/// 1024.
+pub fn parse_file_size(size: &str) -> Option<u64> {
+    let mut words = size.split_whitespace();
+    let number: f64 = words.next()?.parse().ok()?;
+    let multiplier = match words.next().map(str::to_ascii_uppercase).as_deref() {
+        None | Some("B") => 1u64,
+        Some("KB") => 1 << 10,
+        Some("MB") => 1 << 20,
+        Some("GB") => 1 << 30,
+        _ => return None,
+    };
+    if number < 0.0 {
+        return None;
+    }
+    Some((number * multiplier as f64).round() as u64)
+}
+
+/// Formats a file size in bytes the same way the service does, e.g.
+/// "677.17 MB".
+pub fn format_file_size(bytes: u64) -> String {
+    if bytes >= 1 << 30 {
+        format!("{:.2} GB", bytes as f64 / (1u64 << 30) as f64)
+    } else {
+        format!("{:.2} MB", bytes as f64 / (1u64 << 20) as f64)
+    }
+}
+
+/// Parses every release of an AjaxDriverService response. The releases are
+/// returned in the order the service lists them, i.e. newest first.
+///
+/// The percent-encoded fields are decoded and the HTML entities of the name
+/// are replaced with the characters they represent. Hotfix drivers are
+/// tagged as beta releases even if the service does not flag them so.
+pub fn parse_driver_releases(page: &str) -> Result<Vec<DriverRelease>, DriverCheckError> {
+    let data = json::parse(page).map_err(DriverCheckError::MalformedJson)?;
+    data["IDS"]
+        .members()
+        .map(|entry| {
+            let info = &entry["downloadInfo"];
+            let text = |key: &str| {
+                info[key]
+                    .as_str()
+                    .filter(|value| !value.is_empty())
+                    .map(String::from)
+            };
+            let version = text("Version").ok_or(DriverCheckError::MissingField("version"))?;
+            let download_url =
+                text("DownloadURL").ok_or(DriverCheckError::MissingField("download URL"))?;
+            let name = text("Name")
+                .map(|name| decode_field(&name))
+                .unwrap_or_default();
+            let name = html_escape::decode_html_entities(&name).into_owned();
+            let hotfix = name.to_lowercase().contains("hotfix");
+            Ok(DriverRelease {
+                version: version.parse()?,
+                name,
+                release_date: text("ReleaseDateTime").and_then(|date| date.parse().ok()),
+                download_url,
+                file_size: text("DownloadURLFileSize").and_then(|size| parse_file_size(&size)),
+                file_size_exact: false,
+                whql: info["IsWHQL"] == "1",
+                beta: info["IsBeta"] == "1" || hotfix,
+                recommended: info["IsRecommended"] == "1",
+                release_notes_url: text("ReleaseNotes"),
+                details_url: text("DetailsURL"),
+                other_notes: text("OtherNotes").map(|notes| decode_field(&notes)),
+                brief_description: text("BriefDescription").map(|notes| decode_field(&notes)),
+                sha256: None,
+            })
+        })
+        .collect()
+}
+
+/// Retrieves every driver release returned for the query, newest first. The
+/// number of releases is limited by the number of results of the query.
+///
+/// Takes as an argument a fetcher that is able to retrieve data from the
+/// server. Just use get_page() here.
+pub fn list_available_drivers(
+    fetcher: &impl HttpFetcher,
+    query: &DriverQuery,
+) -> Result<Vec<DriverRelease>, DriverCheckError> {
+    parse_driver_releases(&fetcher.fetch(&query.url())?)
+}
+
+/// Returns the newest stable release and the newest beta release of the
+/// list. The list contains beta releases only if the query included them,
+/// see `DriverQuery::beta()`.
+pub fn latest_stable_and_beta(
+    releases: &[DriverRelease],
+) -> (Option<&DriverRelease>, Option<&DriverRelease>) {
+    let newest = |beta: bool| {
+        releases
+            .iter()
+            .filter(|release| release.beta == beta)
+            .max_by(|a, b| a.version.cmp(&b.version))
+    };
+    (newest(false), newest(true))
+}
+
+/// Detects the channel of the installed driver by looking the installed
+/// version up from the releases of each candidate channel, e.g.
+/// `DriverChannel::GEFORCE`. The other parameters of the query are kept.
+///
+/// The channels are looked up in order until the version is found. Returns
+/// `None` if the version is not found from any of the channels, and an error
+/// if a lookup fails.
+pub fn detect_channel(
+    fetcher: &impl HttpFetcher,
+    query: &DriverQuery,
+    installed: &DriverVersion,
+    candidates: &[DriverChannel],
+) -> Result<Option<DriverChannel>, DriverCheckError> {
+    for channel in candidates {
+        let query = query.clone().channel(*channel);
+        let releases = list_available_drivers(fetcher, &query)?;
+        if releases.iter().any(|release| release.version == *installed) {
+            return Ok(Some(*channel));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stub function for unit tests. Serves the saved JSON fixture.
+    fn get_test_page(_url: &str) -> Result<String, DriverCheckError> {
+        Ok(include_str!("../fixtures/ajax_driver_service.json").to_string())
+    }
+
+    /// Test that every release is returned in order.
+    #[test]
+    fn list_available_drivers_success() {
+        let releases = list_available_drivers(&get_test_page, &DriverQuery::new()).unwrap();
+        let versions: Vec<String> = releases.iter().map(|r| r.version.to_string()).collect();
+        assert_eq!(versions, ["566.14", "565.90", "560.94"]);
+    }
+
+    /// Test that the metadata of a release is parsed and decoded.
+    #[test]
+    fn parse_driver_releases_metadata() {
+        let releases =
+            parse_driver_releases(include_str!("../fixtures/ajax_driver_service.json")).unwrap();
+        let release = &releases[0];
+        assert_eq!(release.version, DriverVersion::new(566, 14));
+        assert_eq!(release.name, "GeForce Game Ready Driver");
+        assert_eq!(release.release_date.unwrap().to_string(), "2024-11-19");
+        assert_eq!(
+            release.download_url,
+            "https://us.download.nvidia.com/Windows/566.14/566.14-desktop-win10-win11-64bit-international-dch-whql.exe"
+        );
+        assert_eq!(release.file_size, Some(710_064_210));
+        assert!(release.whql);
+        assert!(!release.beta);
+        assert!(!release.recommended);
+        assert_eq!(
+            release.release_notes_url.as_deref(),
+            Some("https://us.download.nvidia.com/Windows/566.14/566.14-win11-win10-release-notes.pdf")
+        );
+        assert_eq!(
+            release.details_url.as_deref(),
+            Some("https://www.nvidia.com/en-us/drivers/details/232817/")
+        );
+        assert_eq!(
+            release.other_notes.as_deref(),
+            Some("<p><b>Game Ready for Microsoft Flight Simulator 2024</b></p>")
+        );
+        assert!(release
+            .brief_description
+            .as_deref()
+            .unwrap()
+            .starts_with("<p>This new Game Ready Driver provides"));
+        assert!(releases[1].recommended);
+        assert_eq!(releases[1].other_notes, None);
+        assert!(releases[2].beta);
+        assert!(!releases[2].whql);
+        assert_eq!(releases[2].name, "GeForce Hotfix Driver");
+        assert_eq!(releases[2].file_size, Some(1_288_490_189));
+        assert_eq!(releases[2].details_url, None);
+    }
+
+    /// Test that both release date forms are parsed.
+    #[test]
+    fn release_date_parse() {
+        let date: ReleaseDate = "Tue Aug 20, 2024".parse().unwrap();
+        assert_eq!(
+            date,
+            ReleaseDate {
+                year: 2024,
+                month: 8,
+                day: 20
+            }
+        );
+        assert_eq!("2024-08-20".parse::<ReleaseDate>().unwrap(), date);
+        assert!("Tue Foo 20, 2024".parse::<ReleaseDate>().is_err());
+        assert!("yesterday".parse::<ReleaseDate>().is_err());
+        assert!(date < "Tue Nov 19, 2024".parse().unwrap());
+    }
+
+    /// Test that a date missing from the calendar is a parse error.
+    #[test]
+    fn release_date_parse_out_of_range() {
+        for date in [
+            "2024-13-01",
+            "2024-00-10",
+            "2024-02-31",
+            "2023-02-29",
+            "2024-04-31",
+            "Tue Nov 32, 2024",
+        ] {
+            assert!(
+                matches!(date.parse::<ReleaseDate>(), Err(DriverCheckError::ParseDate(ref s)) if s == date),
+                "{date}"
+            );
+        }
+        assert_eq!(
+            "2024-02-29".parse::<ReleaseDate>().unwrap().to_string(),
+            "2024-02-29"
+        );
+    }
+
+    /// Test that file sizes are converted to bytes and back.
+    #[test]
+    fn file_size_parse_and_format() {
+        assert_eq!(parse_file_size("677.17 MB"), Some(710_064_210));
+        assert_eq!(parse_file_size("850 KB"), Some(870_400));
+        assert_eq!(parse_file_size("12"), Some(12));
+        assert_eq!(parse_file_size("big"), None);
+        assert_eq!(parse_file_size("12 PB"), None);
+        assert_eq!(format_file_size(710_064_210), "677.17 MB");
+        assert_eq!(format_file_size(1_288_490_189), "1.20 GB");
+    }
+
+    /// Test that the channel is detected from the channel having the
+    /// installed version.
+    #[test]
+    fn detect_channel_success() {
+        let fetcher = |url: &str| {
+            let version = if url.contains("upCRD=1") {
+                "565.90"
+            } else {
+                "566.14"
+            };
+            Ok(format!(
+                r#"{{ "IDS" : [ {{ "downloadInfo": {{ "Version" : "{version}", "DownloadURL" : "https://example.com/{version}.exe" }} }} ] }}"#
+            ))
+        };
+        let query = DriverQuery::new();
+        let detect = |version: &str| {
+            detect_channel(
+                &fetcher,
+                &query,
+                &version.parse().unwrap(),
+                &DriverChannel::GEFORCE,
+            )
+            .unwrap()
+        };
+        assert_eq!(detect("566.14"), Some(DriverChannel::GameReady));
+        assert_eq!(detect("565.90"), Some(DriverChannel::Studio));
+        assert_eq!(detect("552.12"), None);
+    }
+
+    /// Test that the lookups stop at the first matching channel and that a
+    /// failing lookup is an error rather than a wrong channel.
+    #[test]
+    fn detect_channel_first_match_and_failure() {
+        let urls = std::cell::RefCell::new(Vec::new());
+        let fetcher = |url: &str| {
+            urls.borrow_mut().push(url.to_string());
+            get_test_page(url)
+        };
+        let installed = DriverVersion::new(566, 14);
+        let channel = detect_channel(
+            &fetcher,
+            &DriverQuery::new(),
+            &installed,
+            &DriverChannel::GEFORCE,
+        );
+        assert_eq!(channel.unwrap(), Some(DriverChannel::GameReady));
+        assert_eq!(urls.borrow().len(), 1);
+
+        let failing = |_: &str| -> Result<String, DriverCheckError> {
+            Err(DriverCheckError::MissingField("test"))
+        };
+        assert!(detect_channel(
+            &failing,
+            &DriverQuery::new(),
+            &installed,
+            &DriverChannel::GEFORCE
+        )
+        .is_err());
+    }
+
+    /// Test that a hotfix driver is tagged as beta even without the flag.
+    #[test]
+    fn parse_driver_releases_hotfix() {
+        let releases = parse_driver_releases(
+            r#"{ "IDS" : [ { "downloadInfo" : { "Version" : "566.45", "Name" : "GeForce%20Hotfix%20Driver", "IsBeta" : "0", "DownloadURL" : "https://example.com/566.45.exe" } } ] }"#,
+        )
+        .unwrap();
+        assert!(releases[0].beta);
+    }
+
+    /// Test that the newest stable and beta releases are found.
+    #[test]
+    fn latest_stable_and_beta_success() {
+        let releases = parse_driver_releases(&get_test_page("").unwrap()).unwrap();
+        let (stable, beta) = latest_stable_and_beta(&releases);
+        assert_eq!(stable.unwrap().version, DriverVersion::new(566, 14));
+        assert_eq!(beta.unwrap().version, DriverVersion::new(560, 94));
+        let (stable, beta) = latest_stable_and_beta(&releases[..2]);
+        assert_eq!(stable.unwrap().version, DriverVersion::new(566, 14));
+        assert!(beta.is_none());
+        let (stable, beta) = latest_stable_and_beta(&[]);
+        assert!(stable.is_none() && beta.is_none());
+    }
+
+    /// Test that a response without releases gives an empty list.
+    #[test]
+    fn parse_driver_releases_empty() {
+        assert!(parse_driver_releases(r#"{ "Success" : "0" }"#)
+            .unwrap()
+            .is_empty());
+        assert!(parse_driver_releases(r#"{ "IDS" : [] }"#)
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Test that a release without download URL is reported.
+    #[test]
+    fn parse_driver_releases_missing_url() {
+        assert!(matches!(
+            parse_driver_releases(
+                r#"{ "IDS" : [ { "downloadInfo": { "Version" : "566.14" } } ] }"#
+            ),
+            Err(DriverCheckError::MissingField("download URL"))
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+//! Machine-readable report of the update check, e.g. for dashboards.
+
+use crate::{DriverChannel, DriverRelease, DriverVersion, GpuInfo, ReleaseDate};
+use serde::Serialize;
+
+/// Result of an update check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpdateReport {
+    pub installed_version: DriverVersion,
+    /// Channel of the installed driver, if the source has channels.
+    pub channel: Option<DriverChannel>,
+    /// Names of the installed GPUs.
+    pub gpus: Vec<String>,
+    /// Version of the latest stable driver.
+    pub available_version: Option<DriverVersion>,
+    pub download_url: Option<String>,
+    pub release_date: Option<ReleaseDate>,
+    /// Version of the latest beta driver, if beta drivers were looked for.
+    pub beta_version: Option<DriverVersion>,
+    pub beta_download_url: Option<String>,
+    /// Tells whether a newer stable or beta driver than the installed one is
+    /// available.
+    pub update_available: bool,
+}
+
+impl UpdateReport {
+    /// Creates the report from the installed driver and the latest available
+    /// releases.
+    pub fn new(
+        installed_version: DriverVersion,
+        channel: Option<DriverChannel>,
+        gpus: &[GpuInfo],
+        latest: Option<&DriverRelease>,
+        beta: Option<&DriverRelease>,
+    ) -> UpdateReport {
+        let newer = |release: Option<&DriverRelease>| {
+            release.is_some_and(|release| installed_version < release.version)
+        };
+        UpdateReport {
+            installed_version,
+            channel,
+            gpus: gpus.iter().map(|gpu| gpu.name.clone()).collect(),
+            available_version: latest.map(|release| release.version),
+            download_url: latest.map(|release| release.download_url.clone()),
+            release_date: latest.and_then(|release| release.release_date),
+            beta_version: beta.map(|release| release.version),
+            beta_download_url: beta.map(|release| release.download_url.clone()),
+            update_available: newer(latest) || newer(beta),
+        }
+    }
+
+    /// Returns the report as a pretty-printed JSON document.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report is always serializable")
+    }
+
+    /// Returns the report as CSV with a header line and a single record. The
+    /// GPU names are separated with "; " and the missing values are empty.
+    pub fn to_csv(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let fields = [
+            ("installed_version", self.installed_version.to_string()),
+            ("channel", optional(self.channel.map(|c| c.to_string()))),
+            ("gpus", self.gpus.join("; ")),
+            (
+                "available_version",
+                optional(self.available_version.map(|v| v.to_string())),
+            ),
+            ("download_url", optional(self.download_url.clone())),
+            (
+                "release_date",
+                optional(self.release_date.map(|d| d.to_string())),
+            ),
+            (
+                "beta_version",
+                optional(self.beta_version.map(|v| v.to_string())),
+            ),
+            (
+                "beta_download_url",
+                optional(self.beta_download_url.clone()),
+            ),
+            ("update_available", self.update_available.to_string()),
+        ];
+        let header: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        let record: Vec<String> = fields.iter().map(|(_, value)| csv_field(value)).collect();
+        format!("{}\r\n{}\r\n", header.join(","), record.join(","))
+    }
+}
+
+/// Quotes the CSV field if it contains separators, quotes or line breaks.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_driver_releases;
+
+    /// Returns a report of the fixture releases for an installed 565.90.
+    fn test_report() -> UpdateReport {
+        let releases =
+            parse_driver_releases(include_str!("../fixtures/ajax_driver_service.json")).unwrap();
+        let gpus = crate::parse_gpu_query(include_str!("../fixtures/smi_query_multi.csv")).unwrap();
+        UpdateReport::new(
+            DriverVersion::new(565, 90),
+            Some(DriverChannel::GameReady),
+            &gpus,
+            releases.first(),
+            None,
+        )
+    }
+
+    /// Test that the report has the available driver.
+    #[test]
+    fn update_report_new() {
+        let report = test_report();
+        assert!(report.update_available);
+        assert_eq!(report.available_version, Some(DriverVersion::new(566, 14)));
+        assert_eq!(report.release_date.unwrap().to_string(), "2024-11-19");
+        assert_eq!(report.gpus[0], "NVIDIA GeForce RTX 4070");
+        let report = UpdateReport::new(DriverVersion::new(566, 14), None, &[], None, None);
+        assert!(!report.update_available);
+    }
+
+    /// Test that the report is serialized as JSON with plain strings.
+    #[test]
+    fn update_report_json() {
+        let report = test_report();
+        let value: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(value["installed_version"], "565.90");
+        assert_eq!(value["available_version"], "566.14");
+        assert_eq!(value["channel"], "Game Ready");
+        assert_eq!(value["release_date"], "2024-11-19");
+        assert_eq!(value["beta_version"], serde_json::Value::Null);
+        assert_eq!(value["update_available"], true);
+        assert_eq!(value["gpus"][1], "NVIDIA RTX A4000");
+    }
+
+    /// Test that the report is serialized as CSV.
+    #[test]
+    fn update_report_csv() {
+        let report = UpdateReport::new(DriverVersion::new(566, 14), None, &[], None, None);
+        assert_eq!(
+            report.to_csv(),
+            "installed_version,channel,gpus,available_version,download_url,release_date,\
+             beta_version,beta_download_url,update_available\r\n\
+             566.14,,,,,,,,false\r\n"
+        );
+        assert_eq!(csv_field("GeForce, \"Ti\""), "\"GeForce, \"\"Ti\"\"\"");
+        assert!(test_report().to_csv().contains(",566.14,"));
+    }
+}
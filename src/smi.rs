@@ -0,0 +1,526 @@
+//! Installed driver and GPU information from nvidia-smi.
+
+use crate::{DriverCheckError, DriverVersion};
+use regex::Regex;
+use std::fmt;
+use std::{env, fs, path::Path, path::PathBuf, process::Command};
+
+/// Standard nvidia-smi locations under Linux, searched after `PATH`.
+const LINUX_SMI_DIRS: [&str; 3] = ["/usr/bin", "/usr/local/bin", "/usr/local/nvidia/bin"];
+
+/// Linux kernel module information files containing the driver version.
+pub const KERNEL_MODULE_VERSION_FILES: [&str; 2] =
+    ["/proc/driver/nvidia/version", "/sys/module/nvidia/version"];
+
+/// Columns queried from nvidia-smi, in the order they are parsed.
+const GPU_QUERY: &str = "index,name,uuid,pci.bus_id,pci.device_id,memory.total,driver_version,vbios_version,driver_model.current";
+
+/// A GPU reported by nvidia-smi.
+///
+/// Values nvidia-smi reports as not available (e.g. "[N/A]" or
+/// "[Unknown Error]") are represented as `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuInfo {
+    pub index: u32,
+    /// Product name, e.g. "NVIDIA GeForce GTX 1070 Ti".
+    pub name: String,
+    pub uuid: String,
+    /// PCI bus ID, e.g. "00000000:01:00.0".
+    pub pci_bus_id: String,
+    /// PCI device ID, e.g. 0x1B82.
+    pub pci_device_id: u16,
+    /// Total memory in MiB.
+    pub memory_total: Option<u64>,
+    pub driver_version: Option<DriverVersion>,
+    pub vbios_version: Option<String>,
+    /// Driver model, e.g. "WDDM". Only available under Windows.
+    pub driver_model: Option<String>,
+}
+
+/// A GPU whose driver state differs from the other GPUs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverMismatch {
+    pub index: u32,
+    pub name: String,
+    /// Driver version reported for this GPU, if any.
+    pub driver_version: Option<DriverVersion>,
+    /// Newest driver version reported for the other GPUs.
+    pub expected: DriverVersion,
+}
+
+impl fmt::Display for DriverMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.driver_version {
+            Some(version) => write!(
+                f,
+                "GPU {} ({}) uses driver version {} instead of {}",
+                self.index, self.name, version, self.expected
+            ),
+            None => write!(
+                f,
+                "GPU {} ({}) does not report a driver version, expected {}",
+                self.index, self.name, self.expected
+            ),
+        }
+    }
+}
+
+/// Installed driver information from the XML output of nvidia-smi
+/// (`nvidia-smi -q -x`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmiLog {
+    pub driver_version: DriverVersion,
+    /// CUDA version supported by the driver, e.g. "12.6".
+    pub cuda_version: Option<String>,
+    pub gpus: Vec<SmiLogGpu>,
+}
+
+/// A GPU in the XML output of nvidia-smi.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmiLogGpu {
+    /// Product name, e.g. "NVIDIA GeForce GTX 1070 Ti".
+    pub product_name: String,
+    /// Product brand, e.g. "GeForce".
+    pub product_brand: Option<String>,
+    /// Driver model, e.g. "WDDM". Only available under Windows.
+    pub driver_model: Option<String>,
+}
+
+/// Parses the XML output of `nvidia-smi -q -x`.
+pub fn parse_smi_log(xml: &str) -> Result<SmiLog, DriverCheckError> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    };
+    let document = roxmltree::Document::parse_with_options(xml, options)
+        .map_err(DriverCheckError::MalformedXml)?;
+    let root = document.root_element();
+    if !root.has_tag_name("nvidia_smi_log") {
+        return Err(DriverCheckError::SmiOutput);
+    }
+    let child_text = |node: roxmltree::Node, tag: &str| {
+        node.children()
+            .find(|child| child.has_tag_name(tag))
+            .and_then(|child| child.text())
+            .map(str::trim)
+            .filter(|text| !text.is_empty() && *text != "N/A")
+            .map(String::from)
+    };
+    let driver_version = child_text(root, "driver_version")
+        .ok_or(DriverCheckError::SmiOutput)?
+        .parse()?;
+    let gpus = root
+        .children()
+        .filter(|node| node.has_tag_name("gpu"))
+        .map(|gpu| SmiLogGpu {
+            product_name: child_text(gpu, "product_name").unwrap_or_default(),
+            product_brand: child_text(gpu, "product_brand"),
+            driver_model: gpu
+                .children()
+                .find(|child| child.has_tag_name("driver_model"))
+                .and_then(|model| child_text(model, "current_dm")),
+        })
+        .collect();
+    Ok(SmiLog {
+        driver_version,
+        cuda_version: child_text(root, "cuda_version"),
+        gpus,
+    })
+}
+
+/// Retrieves installed driver information by running `nvidia-smi -q -x`.
+///
+/// If nvidia-smi cannot be found or executed, or its output is not valid,
+/// then an error is provided as a result.
+pub fn get_smi_log(executable_name: &str) -> Result<SmiLog, DriverCheckError> {
+    let nvidiasmi = get_nvidia_smi_location(executable_name)?;
+    let output = Command::new(nvidiasmi)
+        .args(["-q", "-x"])
+        .output()
+        .map_err(DriverCheckError::SmiFailed)?;
+    parse_smi_log(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Retrieves installed display driver version.
+///
+/// The version is read from the XML output of nvidia-smi. If that fails,
+/// then the version is scraped from the human-readable output instead. Under
+/// Linux the version is finally read from the kernel module information
+/// files, so that the version is found even without nvidia-smi.
+///
+/// If the version number is not available (e.g. nvidia-smi.exe could not be
+/// found), then an error is provided as a result.
+pub fn get_installed_version(executable_name: &str) -> Result<DriverVersion, DriverCheckError> {
+    if let Ok(log) = get_smi_log(executable_name) {
+        return Ok(log.driver_version);
+    }
+    let result = get_smi_banner_version(executable_name);
+    if cfg!(target_os = "linux") && result.is_err() {
+        if let Some(version) = KERNEL_MODULE_VERSION_FILES
+            .iter()
+            .find_map(|path| get_kernel_module_version(Path::new(path)).ok())
+        {
+            return Ok(version);
+        }
+    }
+    result
+}
+
+/// Scrapes the driver version from the human-readable output of nvidia-smi.
+fn get_smi_banner_version(executable_name: &str) -> Result<DriverVersion, DriverCheckError> {
+    let nvidiasmi = get_nvidia_smi_location(executable_name)?;
+    let output = Command::new(nvidiasmi)
+        .output()
+        .map_err(DriverCheckError::SmiFailed)?;
+    let pattern = Regex::new(r"Driver Version: ([0-9]+\.[0-9]+(?:\.[0-9]+)?)").unwrap();
+    let nvsmi = String::from_utf8_lossy(&output.stdout);
+    let captures = pattern
+        .captures(&nvsmi)
+        .ok_or(DriverCheckError::SmiOutput)?;
+    captures[1].parse()
+}
+
+/// Reads the driver version from a Linux kernel module information file,
+/// i.e. one of `KERNEL_MODULE_VERSION_FILES`. The path can be changed for
+/// testing.
+pub fn get_kernel_module_version(path: &Path) -> Result<DriverVersion, DriverCheckError> {
+    let contents = fs::read_to_string(path).map_err(DriverCheckError::DriverFile)?;
+    parse_kernel_module_version(&contents)
+}
+
+/// Parses the contents of `/proc/driver/nvidia/version` (e.g. "NVRM version:
+/// NVIDIA UNIX x86_64 Kernel Module  550.54.14  Thu Feb 22 ...") or
+/// `/sys/module/nvidia/version` (e.g. "550.54.14").
+pub fn parse_kernel_module_version(contents: &str) -> Result<DriverVersion, DriverCheckError> {
+    let contents = contents.trim();
+    if contents.starts_with("NVRM version:") {
+        let pattern = Regex::new(r"\s([0-9]+\.[0-9]+(?:\.[0-9]+)?)\s").unwrap();
+        let first_line = contents.lines().next().unwrap_or_default();
+        let captures = pattern
+            .captures(first_line)
+            .ok_or(DriverCheckError::SmiOutput)?;
+        captures[1].parse()
+    } else {
+        contents.parse()
+    }
+}
+
+/// Returns the directories searched for nvidia-smi, in the order they are
+/// searched.
+///
+/// The Windows driver locations are used whenever the `windir` and
+/// `ProgramFiles` environment variables are set. Then the directories in
+/// `PATH` are searched, and finally the standard locations under Linux.
+fn nvidia_smi_search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(windir) = env::var_os("windir") {
+        dirs.push([windir.as_os_str(), "System32".as_ref()].iter().collect());
+    }
+    if let Some(program_files) = env::var_os("ProgramFiles") {
+        dirs.push(
+            [
+                program_files.as_os_str(),
+                "NVIDIA Corporation".as_ref(),
+                "NVSMI".as_ref(),
+            ]
+            .iter()
+            .collect(),
+        );
+    }
+    if let Some(path) = env::var_os("PATH") {
+        dirs.extend(env::split_paths(&path));
+    }
+    if !cfg!(windows) {
+        dirs.extend(LINUX_SMI_DIRS.iter().map(PathBuf::from));
+    }
+    dirs
+}
+
+/// Finds the executable from the given directories and returns the full
+/// path of the first match.
+///
+/// A name with a directory, e.g. a configured "/opt/nvidia/bin/nvidia-smi"
+/// or "bin/nvidia-smi", is checked as such before the directories, so that
+/// it is found even if there are no directories to search.
+pub fn find_executable(executable_name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let path = Path::new(executable_name);
+    if path.components().count() > 1 && path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    dirs.iter()
+        .map(|dir| dir.join(executable_name))
+        .find(|path| path.is_file())
+}
+
+/// Find nvidia-smi and return full path.
+fn get_nvidia_smi_location(executable_name: &str) -> Result<String, DriverCheckError> {
+    find_executable(executable_name, &nvidia_smi_search_dirs())
+        .map(|path| String::from(path.to_string_lossy()))
+        .ok_or(DriverCheckError::SmiNotFound)
+}
+
+/// Retrieves the GPUs seen by nvidia-smi with a single nvidia-smi call, so
+/// that the driver matching the actual cards can be looked up.
+///
+/// If nvidia-smi cannot be found or executed, then an error is provided as a
+/// result.
+pub fn get_installed_gpus(executable_name: &str) -> Result<Vec<GpuInfo>, DriverCheckError> {
+    let nvidiasmi = get_nvidia_smi_location(executable_name)?;
+    let output = Command::new(nvidiasmi)
+        .arg(format!("--query-gpu={GPU_QUERY}"))
+        .arg("--format=csv,noheader,nounits")
+        .output()
+        .map_err(DriverCheckError::SmiFailed)?;
+    parse_gpu_query(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Returns the value of a CSV field, or `None` if nvidia-smi reports it as
+/// not available.
+fn available(field: &str) -> Option<&str> {
+    if field.is_empty() || field.starts_with('[') {
+        None
+    } else {
+        Some(field)
+    }
+}
+
+/// Parses the CSV output of nvidia-smi `--query-gpu` with the columns
+/// index, name, uuid, pci.bus_id, pci.device_id, memory.total,
+/// driver_version, vbios_version and driver_model.current. The header line
+/// is skipped if present and the units of memory.total are ignored.
+pub fn parse_gpu_query(output: &str) -> Result<Vec<GpuInfo>, DriverCheckError> {
+    let gpus = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("index,"))
+        .map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() < 9 {
+                return Err(DriverCheckError::SmiOutput);
+            }
+            let pci_id = fields[4].trim_start_matches("0x").trim_start_matches("0X");
+            let pci_id = u32::from_str_radix(pci_id, 16).or(Err(DriverCheckError::SmiOutput))?;
+            Ok(GpuInfo {
+                index: fields[0].parse().or(Err(DriverCheckError::SmiOutput))?,
+                name: fields[1].to_string(),
+                uuid: fields[2].to_string(),
+                pci_bus_id: fields[3].to_string(),
+                pci_device_id: (pci_id >> 16) as u16,
+                memory_total: available(fields[5])
+                    .and_then(|memory| memory.trim_end_matches("MiB").trim().parse().ok()),
+                driver_version: available(fields[6]).and_then(|version| version.parse().ok()),
+                vbios_version: available(fields[7]).map(String::from),
+                driver_model: available(fields[8]).map(String::from),
+            })
+        })
+        .collect::<Result<Vec<GpuInfo>, DriverCheckError>>()?;
+    if gpus.is_empty() {
+        Err(DriverCheckError::SmiOutput)
+    } else {
+        Ok(gpus)
+    }
+}
+
+/// Finds the GPUs whose driver version differs from the newest driver
+/// version reported for any GPU, or which do not report a driver version at
+/// all. An empty list means that all the GPUs are in the same driver state.
+pub fn find_driver_mismatches(gpus: &[GpuInfo]) -> Vec<DriverMismatch> {
+    let expected = match gpus.iter().filter_map(|gpu| gpu.driver_version).max() {
+        Some(version) => version,
+        None => return Vec::new(),
+    };
+    gpus.iter()
+        .filter(|gpu| gpu.driver_version != Some(expected))
+        .map(|gpu| DriverMismatch {
+            index: gpu.index,
+            name: gpu.name.clone(),
+            driver_version: gpu.driver_version,
+            expected,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that a single GPU is parsed from the query output.
+    #[test]
+    fn parse_gpu_query_single() {
+        let gpus = parse_gpu_query(include_str!("../fixtures/smi_query_single.csv")).unwrap();
+        assert_eq!(
+            gpus,
+            vec![GpuInfo {
+                index: 0,
+                name: "NVIDIA GeForce GTX 1070 Ti".to_string(),
+                uuid: "GPU-5f3c2d4e-8a1b-4c6d-9e0f-123456789abc".to_string(),
+                pci_bus_id: "00000000:01:00.0".to_string(),
+                pci_device_id: 0x1B82,
+                memory_total: Some(8192),
+                driver_version: Some(DriverVersion::new(560, 94)),
+                vbios_version: Some("86.04.85.00.63".to_string()),
+                driver_model: Some("WDDM".to_string()),
+            }]
+        );
+        assert!(find_driver_mismatches(&gpus).is_empty());
+    }
+
+    /// Test that the header line is skipped and several GPUs are returned.
+    #[test]
+    fn parse_gpu_query_multi() {
+        let gpus = parse_gpu_query(include_str!("../fixtures/smi_query_multi.csv")).unwrap();
+        assert_eq!(gpus.len(), 3);
+        assert_eq!(gpus[1].index, 1);
+        assert_eq!(gpus[1].name, "NVIDIA RTX A4000");
+        assert_eq!(gpus[1].pci_bus_id, "00000000:21:00.0");
+        assert_eq!(gpus[1].pci_device_id, 0x24B0);
+        assert_eq!(gpus[1].memory_total, Some(16376));
+        assert_eq!(gpus[1].driver_model.as_deref(), Some("TCC"));
+        assert!(find_driver_mismatches(&gpus).is_empty());
+    }
+
+    /// Test that GPUs with differing or missing driver versions are found.
+    #[test]
+    fn find_driver_mismatches_success() {
+        let gpus = parse_gpu_query(include_str!("../fixtures/smi_query_mismatch.csv")).unwrap();
+        assert_eq!(gpus[2].memory_total, None);
+        assert_eq!(gpus[2].driver_version, None);
+        let mismatches = find_driver_mismatches(&gpus);
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].index, 1);
+        assert_eq!(mismatches[0].expected, DriverVersion::new(560, 94));
+        assert_eq!(
+            mismatches[0].to_string(),
+            "GPU 1 (NVIDIA GeForce GTX 1070 Ti) uses driver version 552.12 instead of 560.94"
+        );
+        assert_eq!(
+            mismatches[1].to_string(),
+            "GPU 2 (NVIDIA RTX A4000) does not report a driver version, expected 560.94"
+        );
+    }
+
+    /// Test that the XML output is parsed.
+    #[test]
+    fn parse_smi_log_success() {
+        let log = parse_smi_log(include_str!("../fixtures/smi_log.xml")).unwrap();
+        assert_eq!(
+            log,
+            SmiLog {
+                driver_version: DriverVersion::new(560, 94),
+                cuda_version: Some("12.6".to_string()),
+                gpus: vec![SmiLogGpu {
+                    product_name: "NVIDIA GeForce GTX 1070 Ti".to_string(),
+                    product_brand: Some("GeForce".to_string()),
+                    driver_model: Some("WDDM".to_string()),
+                }],
+            }
+        );
+    }
+
+    /// Test that every GPU of the XML output is returned.
+    #[test]
+    fn parse_smi_log_multi() {
+        let log = parse_smi_log(include_str!("../fixtures/smi_log_multi.xml")).unwrap();
+        assert_eq!(log.driver_version, DriverVersion::new(552, 12));
+        assert_eq!(log.gpus.len(), 2);
+        assert_eq!(log.gpus[1].product_name, "NVIDIA RTX A4000");
+        assert_eq!(log.gpus[1].product_brand.as_deref(), Some("NVIDIA RTX"));
+        assert_eq!(log.gpus[1].driver_model, None);
+    }
+
+    /// Test that the human-readable output is not accepted as XML.
+    #[test]
+    fn parse_smi_log_fail() {
+        assert!(matches!(
+            parse_smi_log("Driver Version: 123.45"),
+            Err(DriverCheckError::MalformedXml(_))
+        ));
+        assert!(matches!(
+            parse_smi_log("<nvidia_smi_log></nvidia_smi_log>"),
+            Err(DriverCheckError::SmiOutput)
+        ));
+        assert!(matches!(
+            parse_smi_log("<other><driver_version>1.2</driver_version></other>"),
+            Err(DriverCheckError::SmiOutput)
+        ));
+    }
+
+    /// Test that the version is read from /proc/driver/nvidia/version.
+    #[test]
+    fn get_kernel_module_version_proc() {
+        let version =
+            get_kernel_module_version(Path::new("fixtures/proc_driver_nvidia_version")).unwrap();
+        assert_eq!(version.to_string(), "550.54.14");
+        let version =
+            get_kernel_module_version(Path::new("fixtures/proc_driver_nvidia_version_open"))
+                .unwrap();
+        assert_eq!(version.to_string(), "560.35.03");
+    }
+
+    /// Test that the version is read from /sys/module/nvidia/version.
+    #[test]
+    fn get_kernel_module_version_sys() {
+        let version =
+            get_kernel_module_version(Path::new("fixtures/sys_module_nvidia_version")).unwrap();
+        assert_eq!(version.to_string(), "535.104.05");
+    }
+
+    /// Test that a missing file and garbage contents are reported.
+    #[test]
+    fn get_kernel_module_version_fail() {
+        assert!(matches!(
+            get_kernel_module_version(Path::new("fixtures/nonexistent")),
+            Err(DriverCheckError::DriverFile(_))
+        ));
+        assert!(matches!(
+            parse_kernel_module_version("NVRM version: unknown"),
+            Err(DriverCheckError::SmiOutput)
+        ));
+        assert!(parse_kernel_module_version("").is_err());
+    }
+
+    /// Test that the executable is found from the first directory having it.
+    #[test]
+    fn find_executable_success() {
+        let dirs = [PathBuf::from("fixtures"), PathBuf::from("System32")];
+        assert_eq!(
+            find_executable("smi-stub.bat", &dirs),
+            Some(PathBuf::from("System32").join("smi-stub.bat"))
+        );
+        assert_eq!(find_executable("nonexistent", &dirs), None);
+        assert_eq!(find_executable("System32", &[PathBuf::from(".")]), None);
+
+        // A path is found without any directories to search.
+        let path = PathBuf::from("System32").join("smi-stub.bat");
+        let name = path.to_str().unwrap();
+        assert_eq!(find_executable(name, &[]), Some(path.clone()));
+        let absolute = std::env::current_dir().unwrap().join(&path);
+        assert_eq!(
+            find_executable(absolute.to_str().unwrap(), &[]),
+            Some(absolute)
+        );
+        assert_eq!(find_executable("smi-stub.bat", &[]), None);
+    }
+
+    /// Test that garbage output is reported.
+    #[test]
+    fn parse_gpu_query_fail() {
+        assert!(parse_gpu_query("").is_err());
+        assert!(parse_gpu_query("No devices were found").is_err());
+        assert!(parse_gpu_query(
+            "0, NVIDIA GeForce RTX 4070, GPU-0, 00000000:01:00.0, bogus, 12282, 560.94, 95.04, WDDM"
+        )
+        .is_err());
+    }
+
+    /// Test that the GPUs are queried from nvidia-smi.
+    #[test]
+    #[cfg(windows)]
+    fn get_installed_gpus_success() {
+        std::env::set_var("windir", ".");
+        std::env::set_var("ProgramFiles", ".");
+        let gpus = get_installed_gpus("smi-query-stub.bat").unwrap();
+        assert_eq!(gpus[0].name, "NVIDIA GeForce GTX 1070 Ti");
+        assert_eq!(gpus[0].driver_version, Some(DriverVersion::new(123, 45)));
+    }
+}
@@ -0,0 +1,174 @@
+//! Pluggable sources for the available driver information.
+
+use crate::{
+    get_linux_version_information, list_available_drivers, list_linux_drivers, DriverCheckError,
+    DriverQuery, DriverRelease, LinuxFeed,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Retrieves pages from a server.
+///
+/// Any function or closure taking the URL and returning the page contents
+/// is a fetcher, e.g. get_page().
+pub trait HttpFetcher {
+    /// Fetches contents of the URL and returns them as a string.
+    fn fetch(&self, url: &str) -> Result<String, DriverCheckError>;
+}
+
+impl<F> HttpFetcher for F
+where
+    F: Fn(&str) -> Result<String, DriverCheckError>,
+{
+    fn fetch(&self, url: &str) -> Result<String, DriverCheckError> {
+        self(url)
+    }
+}
+
+/// Fetcher remembering the fetched pages, so that a page needed twice in a
+/// run, e.g. for detecting the channel and for the update check, is fetched
+/// only once. The failed fetches are not remembered.
+pub struct MemoFetcher<F: HttpFetcher> {
+    fetcher: F,
+    pages: RefCell<HashMap<String, String>>,
+}
+
+impl<F: HttpFetcher> MemoFetcher<F> {
+    pub fn new(fetcher: F) -> MemoFetcher<F> {
+        MemoFetcher {
+            fetcher,
+            pages: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<F: HttpFetcher> HttpFetcher for MemoFetcher<F> {
+    fn fetch(&self, url: &str) -> Result<String, DriverCheckError> {
+        if let Some(page) = self.pages.borrow().get(url) {
+            return Ok(page.clone());
+        }
+        let page = self.fetcher.fetch(url)?;
+        self.pages
+            .borrow_mut()
+            .insert(url.to_string(), page.clone());
+        Ok(page)
+    }
+}
+
+/// Provides the latest available driver.
+pub trait DriverSource {
+    /// Retrieves the latest available driver release.
+    fn latest(&self) -> Result<DriverRelease, DriverCheckError>;
+
+    /// Retrieves every available driver release, newest first.
+    fn releases(&self) -> Result<Vec<DriverRelease>, DriverCheckError>;
+}
+
+/// Windows drivers from the NVIDIA AjaxDriverService JSON API.
+pub struct NvidiaApiSource<F: HttpFetcher> {
+    pub fetcher: F,
+    pub query: DriverQuery,
+}
+
+impl<F: HttpFetcher> NvidiaApiSource<F> {
+    pub fn new(fetcher: F, query: DriverQuery) -> NvidiaApiSource<F> {
+        NvidiaApiSource { fetcher, query }
+    }
+}
+
+impl<F: HttpFetcher> DriverSource for NvidiaApiSource<F> {
+    fn latest(&self) -> Result<DriverRelease, DriverCheckError> {
+        self.releases()?
+            .into_iter()
+            .next()
+            .ok_or(DriverCheckError::MissingField("version"))
+    }
+
+    fn releases(&self) -> Result<Vec<DriverRelease>, DriverCheckError> {
+        list_available_drivers(&self.fetcher, &self.query)
+    }
+}
+
+/// Linux drivers from NVIDIA's Unix driver index.
+pub struct LinuxFeedSource<F: HttpFetcher> {
+    pub fetcher: F,
+    pub feed: LinuxFeed,
+}
+
+impl<F: HttpFetcher> LinuxFeedSource<F> {
+    pub fn new(fetcher: F, feed: LinuxFeed) -> LinuxFeedSource<F> {
+        LinuxFeedSource { fetcher, feed }
+    }
+}
+
+impl<F: HttpFetcher> DriverSource for LinuxFeedSource<F> {
+    fn latest(&self) -> Result<DriverRelease, DriverCheckError> {
+        let (version, url) = get_linux_version_information(&self.fetcher, &self.feed)?;
+        Ok(DriverRelease::new(version, &url))
+    }
+
+    fn releases(&self) -> Result<Vec<DriverRelease>, DriverCheckError> {
+        list_linux_drivers(&self.fetcher, &self.feed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+    use crate::{get_page, DriverVersion};
+
+    /// Test that a closure capturing state can be used as a fetcher.
+    #[test]
+    fn http_fetcher_closure() {
+        let json = r#"{ "IDS" : [ { "downloadInfo": { "Version" : "560.94", "DownloadURL" : "https://example.com/560.94.exe" } } ] }"#;
+        let fetcher = move |_: &str| Ok(json.to_string());
+        let source = NvidiaApiSource::new(fetcher, DriverQuery::new());
+        let release = source.latest().unwrap();
+        assert_eq!(release.version, DriverVersion::new(560, 94));
+        assert_eq!(release.download_url, "https://example.com/560.94.exe");
+        assert_eq!(source.releases().unwrap().len(), 1);
+    }
+
+    /// Test that a page is fetched once, unless fetching it fails.
+    #[test]
+    fn memo_fetcher_fetch_once() {
+        let calls = RefCell::new(Vec::new());
+        let fetcher = MemoFetcher::new(|url: &str| {
+            calls.borrow_mut().push(url.to_string());
+            match url {
+                "a" => Ok("page a".to_string()),
+                _ => Err(DriverCheckError::MissingField("test")),
+            }
+        });
+        assert_eq!(fetcher.fetch("a").unwrap(), "page a");
+        assert_eq!(fetcher.fetch("a").unwrap(), "page a");
+        assert!(fetcher.fetch("b").is_err());
+        assert!(fetcher.fetch("b").is_err());
+        assert_eq!(calls.into_inner(), ["a", "b", "b"]);
+    }
+
+    /// Test that the sources can be used through the trait object.
+    #[test]
+    fn driver_source_dyn() {
+        let server = TestServer::serve(&[(
+            "/latest.txt",
+            "550.127.05 550.127.05/NVIDIA-Linux-x86_64-550.127.05.run",
+        )]);
+        let sources: Vec<Box<dyn DriverSource>> = vec![
+            Box::new(LinuxFeedSource::new(
+                get_page,
+                LinuxFeed::new().base_url(&server.url),
+            )),
+            Box::new(NvidiaApiSource::new(
+                |_: &str| Err(DriverCheckError::MissingField("test")),
+                DriverQuery::new(),
+            )),
+        ];
+        assert_eq!(
+            sources[0].latest().unwrap().version.to_string(),
+            "550.127.05"
+        );
+        assert!(sources[1].latest().is_err());
+    }
+}
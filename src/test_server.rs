@@ -0,0 +1,148 @@
+//! Minimal local HTTP server for unit tests, so that the network code can be
+//! tested without accessing the online resources.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A request received by the test server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Returns the value of the named header, if the request has it. The
+    /// name is case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response sent by the test server.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Creates "200 OK" response with the given body.
+    pub fn ok(body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    /// Creates an empty response with the given status code.
+    pub fn status(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Adds a header to the response.
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// HTTP server running in a background thread until the test process ends.
+/// Every connection is handled in its own thread, so that a stalling
+/// response does not block the other requests.
+pub struct TestServer {
+    /// Base URL of the server with a trailing slash.
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+    /// Starts a server answering every request with the given handler.
+    pub fn start<F>(handler: F) -> TestServer
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&requests);
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let received = Arc::clone(&received);
+                let handler = Arc::clone(&handler);
+                thread::spawn(move || {
+                    if let Some(request) = read_request(&stream) {
+                        received.lock().unwrap().push(request.clone());
+                        write_response(stream, &handler(&request));
+                    }
+                });
+            }
+        });
+        TestServer { url, requests }
+    }
+
+    /// Starts a server serving the given paths. Other paths are answered
+    /// with "404 Not Found".
+    pub fn serve(pages: &[(&str, &str)]) -> TestServer {
+        let pages: Vec<(String, String)> = pages
+            .iter()
+            .map(|(path, body)| (path.to_string(), body.to_string()))
+            .collect();
+        TestServer::start(move |request| {
+            pages
+                .iter()
+                .find(|(path, _)| *path == request.path)
+                .map(|(_, body)| Response::ok(body.as_str()))
+                .unwrap_or_else(|| Response::status(404))
+        })
+    }
+
+    /// Returns the requests received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let path = line.split_whitespace().nth(1)?.to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    Some(Request { path, headers })
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) {
+    let mut head = format!(
+        "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(&response.body);
+}
@@ -0,0 +1,249 @@
+//! Integrity verification of the downloaded driver installation packages.
+
+use crate::DriverCheckError;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Expected size of an installation package in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedSize {
+    /// The exact size, e.g. from a mirror manifest.
+    Exact(u64),
+    /// The size announced by the service, i.e. the DownloadURLFileSize of
+    /// the release. The service rounds the size to two decimals, e.g.
+    /// "677.17 MB", so half of the last decimal is tolerated either way.
+    Announced(u64),
+}
+
+impl ExpectedSize {
+    /// Returns the expected size in bytes.
+    pub fn bytes(&self) -> u64 {
+        match *self {
+            ExpectedSize::Exact(bytes) | ExpectedSize::Announced(bytes) => bytes,
+        }
+    }
+
+    /// Returns true if the actual size matches the expected one.
+    pub fn matches(&self, actual: u64) -> bool {
+        match *self {
+            ExpectedSize::Exact(bytes) => actual == bytes,
+            ExpectedSize::Announced(bytes) => {
+                // The unit of the last decimal, see format_file_size().
+                let unit = if bytes >= 1 << 30 {
+                    1u64 << 30
+                } else {
+                    1 << 20
+                };
+                actual.abs_diff(bytes) <= unit / 200
+            }
+        }
+    }
+}
+
+/// Returns the path of the checksum sidecar file of the installer, i.e. the
+/// installer path with ".sha256" appended.
+pub fn sha256_sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    PathBuf::from(sidecar)
+}
+
+/// Parses a SHA-256 checksum. Accepts either the bare hex digest or the
+/// output of sha256sum, i.e. the digest followed by the file name. The
+/// digest is returned in lowercase.
+pub fn parse_sha256(text: &str) -> Result<String, DriverCheckError> {
+    let digest = text.split_whitespace().next().unwrap_or_default();
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(DriverCheckError::InvalidChecksum(text.trim().to_string()));
+    }
+    Ok(digest.to_lowercase())
+}
+
+/// Calculates the SHA-256 checksum of the file as lowercase hex.
+pub fn sha256_file(path: &Path) -> Result<String, DriverCheckError> {
+    let read_error = |source| DriverCheckError::InstallerFile {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut file = File::open(path).map_err(read_error)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => hasher.update(&buffer[..count]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(read_error(err)),
+        }
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Verifies the downloaded installer and returns its SHA-256 checksum.
+///
+/// The size is compared with the expected size, see `ExpectedSize`. The
+/// checksum is compared with the given one or, if none
+/// is given, with the sidecar file next to the installer, if there is one.
+pub fn verify_installer(
+    path: &Path,
+    expected_size: Option<ExpectedSize>,
+    sha256: Option<&str>,
+) -> Result<String, DriverCheckError> {
+    let size = fs::metadata(path)
+        .map_err(|source| DriverCheckError::InstallerFile {
+            path: path.to_path_buf(),
+            source,
+        })?
+        .len();
+    if let Some(expected) = expected_size {
+        if !expected.matches(size) {
+            return Err(DriverCheckError::SizeMismatch {
+                path: path.to_path_buf(),
+                expected: expected.bytes(),
+                actual: size,
+            });
+        }
+    }
+
+    let expected = match sha256 {
+        Some(sha256) => Some(parse_sha256(sha256)?),
+        None => match fs::read_to_string(sha256_sidecar_path(path)) {
+            Ok(text) => Some(parse_sha256(&text)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(source) => {
+                return Err(DriverCheckError::InstallerFile {
+                    path: sha256_sidecar_path(path),
+                    source,
+                })
+            }
+        },
+    };
+    let actual = sha256_file(path)?;
+    match expected {
+        Some(expected) if expected != actual => Err(DriverCheckError::ChecksumMismatch {
+            path: path.to_path_buf(),
+            expected,
+            actual,
+        }),
+        _ => Ok(actual),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SHA-256 checksum of "hello world".
+    const HELLO_SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    /// Writes "hello world" to a file in a temporary directory.
+    fn hello_file() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("566.14-win11.exe");
+        fs::write(&path, "hello world").unwrap();
+        (dir, path)
+    }
+
+    /// Test that both checksum formats are accepted.
+    #[test]
+    fn parse_sha256_success() {
+        assert_eq!(parse_sha256(HELLO_SHA256).unwrap(), HELLO_SHA256);
+        assert_eq!(
+            parse_sha256(&format!(
+                "{}  566.14-win11.exe\n",
+                HELLO_SHA256.to_uppercase()
+            ))
+            .unwrap(),
+            HELLO_SHA256
+        );
+        assert!(matches!(
+            parse_sha256("abc123"),
+            Err(DriverCheckError::InvalidChecksum(_))
+        ));
+    }
+
+    /// Test that the checksum and the size of a valid file are accepted.
+    #[test]
+    fn verify_installer_success() {
+        let (_dir, path) = hello_file();
+        assert_eq!(sha256_file(&path).unwrap(), HELLO_SHA256);
+        assert_eq!(
+            verify_installer(&path, Some(ExpectedSize::Exact(11)), Some(HELLO_SHA256)).unwrap(),
+            HELLO_SHA256
+        );
+        assert_eq!(verify_installer(&path, None, None).unwrap(), HELLO_SHA256);
+    }
+
+    /// Test that an exact size must match to the byte and an announced
+    /// size with the precision of the service.
+    #[test]
+    fn verify_installer_size() {
+        let (_dir, path) = hello_file();
+        // "677.17 MB" from the service does not match 11 bytes.
+        let result = verify_installer(&path, Some(ExpectedSize::Announced(710_064_210)), None);
+        assert!(matches!(
+            result,
+            Err(DriverCheckError::SizeMismatch {
+                expected: 710_064_210,
+                actual: 11,
+                ..
+            })
+        ));
+        assert!(matches!(
+            verify_installer(&path, Some(ExpectedSize::Exact(12)), None),
+            Err(DriverCheckError::SizeMismatch {
+                expected: 12,
+                actual: 11,
+                ..
+            })
+        ));
+        assert!(verify_installer(&path, Some(ExpectedSize::Announced(12)), None).is_ok());
+
+        // 677.17 MB is 710_064_210 bytes, any size rounding to it matches.
+        let announced = ExpectedSize::Announced(710_064_210);
+        assert!(announced.matches(710_064_210 + 5_000));
+        assert!(announced.matches(710_064_210 - 5_000));
+        assert!(!announced.matches(710_064_210 + 6_000));
+        assert!(ExpectedSize::Announced(1_288_490_189).matches(1_288_490_189 + 5_000_000));
+        assert!(!ExpectedSize::Exact(710_064_210).matches(710_064_211));
+    }
+
+    /// Test that the checksum of the sidecar file is used.
+    #[test]
+    fn verify_installer_sidecar() {
+        let (_dir, path) = hello_file();
+        fs::write(
+            sha256_sidecar_path(&path),
+            format!("{}  566.14-win11.exe\n", "0".repeat(64)),
+        )
+        .unwrap();
+        let result = verify_installer(&path, None, None);
+        match result {
+            Err(DriverCheckError::ChecksumMismatch {
+                expected, actual, ..
+            }) => {
+                assert_eq!(expected, "0".repeat(64));
+                assert_eq!(actual, HELLO_SHA256);
+            }
+            _ => panic!("unexpected result: {result:?}"),
+        }
+        assert!(verify_installer(&path, None, Some(HELLO_SHA256)).is_ok());
+    }
+
+    /// Test that a missing installer is reported.
+    #[test]
+    fn verify_installer_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = verify_installer(&dir.path().join("missing.exe"), None, None);
+        assert!(matches!(
+            result,
+            Err(DriverCheckError::InstallerFile { .. })
+        ));
+    }
+}